@@ -1,25 +1,34 @@
+use std::fmt;
 use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 use bytes::BytesMut;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use url::form_urlencoded;
 
-use crate::error::Result;
+use crate::error::{Error, FormError, JsonError, Result};
+use crate::header::{HeaderMap, HeaderValue};
+use crate::response::Response;
 
 /// Request/Response body
 #[derive(Clone, Debug)]
 pub struct Body {
-    kind: BodyKind
+    kind: BodyKind,
+    content_type: Option<HeaderValue>,
 }
 
 /// match body kind and process
 #[macro_export]
 macro_rules! body_kind {
-    ($kind:expr, $text:ident => $process_text:block, $binary:ident  => $process_bin:block, $empty:ident => $process_ety:block) => {
+    ($kind:expr, $text:ident => $process_text:block, $binary:ident  => $process_bin:block, $empty:ident => $process_ety:block, $stream:ident => $process_stream:block) => {
         match $kind {
             BodyKind::Text($text) => $process_text
             BodyKind::Binary($binary) => $process_bin
             BodyKind::Empty =>  $process_ety
+            BodyKind::Stream($stream) => $process_stream
         }
     };
 }
@@ -33,12 +42,28 @@ impl Body {
     /// create the body use given `kind`
     pub fn new(kind: BodyKind) -> Self {
         Self {
-            kind
+            kind,
+            content_type: None,
         }
     }
 
-    // that's weird right?
-    // pub fn from_form() {}
+    /// Create the Request Body from `value` encoded as `application/x-www-form-urlencoded`,
+    /// `value` can be a list of key/value pairs or a struct with scalar fields.
+    ///
+    /// The pairs are percent-encoded by `url::form_urlencoded`
+    ///
+    /// ```
+    /// use request_rs::produce::*;
+    ///
+    /// let body = Body::form(&[("username", "admin"), ("password", "a&b c")]).unwrap();
+    /// assert_eq!("application/x-www-form-urlencoded", body.content_type().unwrap());
+    /// ```
+    pub fn form<T: Serialize + ?Sized>(value: &T) -> Result<Self> {
+        let mut encoder = form_urlencoded::Serializer::new(String::new());
+        value.serialize(serde_urlencoded::Serializer::new(&mut encoder))
+            .map_err(|e| Error::from(FormError::new(&format!("failed to encode form: {}", e))))?;
+        Ok(Self::from_string(encoder.finish()).with_content_type(HeaderValue::from_static("application/x-www-form-urlencoded")))
+    }
 
     /// Create the Request Body from bytes
     pub fn from_bytes<B: AsRef<[u8]>>(bytes: B) -> Self {
@@ -49,6 +74,27 @@ impl Body {
         Body::new(BodyKind::Text(str.to_owned()))
     }
 
+    /// Create the Request Body serialized from `value` as json,
+    /// the body is sent with `Content-Type: application/json`
+    ///
+    /// ```
+    /// use request_rs::produce::*;
+    /// use serde::Serialize;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Login {
+    ///     username: String,
+    ///     password: String,
+    /// }
+    ///
+    /// let body = Body::json(&Login { username: "admin".to_owned(), password: "123".to_owned() }).unwrap();
+    /// assert_eq!("application/json", body.content_type().unwrap());
+    /// ```
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> Result<Self> {
+        let data = serde_json::to_vec(value)?;
+        Ok(Self::from_vec(data).with_content_type(HeaderValue::from_static("application/json")))
+    }
+
     /// Create the Request Body from string
     pub fn from_string(str: String) -> Self {
         Body::new(BodyKind::Text(str.to_owned()))
@@ -59,44 +105,150 @@ impl Body {
         Body::new(BodyKind::Binary(BytesMut::from(vec.as_slice())))
     }
 
-    /// Create the Request Body from file
+    /// Create the Request Body read from `reader`, the body is sent with `Transfer-Encoding: chunked`
+    ///
+    /// ```
+    /// use std::io::{self, Read};
+    /// use request_rs::produce::*;
+    ///
+    /// let generated = std::io::repeat(b'a').take(1024 * 1024);
+    /// let body = Body::from_reader(generated);
+    /// ```
+    pub fn from_reader<R: Read + Send + 'static>(reader: R) -> Self {
+        Body::new(BodyKind::Stream(BodyStream::new(reader)))
+    }
+
+    /// Create the Request Body read from `reader` with known length, the body is sent with `Content-Length`
+    pub fn from_sized_reader<R: Read + Send + 'static>(reader: R, length: u64) -> Self {
+        Body::new(BodyKind::Stream(BodyStream::new(reader).with_length(length)))
+    }
+
+    /// Create the Request Body from file, the file is read while sending,
+    /// the size of file is used as `Content-Length`.
+    ///
+    /// The file is opened again if the body is resent, e.g. by redirect or authentication
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let mut file = File::open(path)?;
-        let mut data = Vec::new();
-        file.read_to_end(&mut data)?;
-        Ok(Self::from_vec(data))
+        let file = File::open(path.as_ref())?;
+        let length = file.metadata()?.len();
+        let stream = BodyStream::new(file).with_length(length).reopen_from(path.as_ref().to_path_buf());
+        Ok(Body::new(BodyKind::Stream(stream)))
     }
 
-    /// return the Body length
+    /// return the Body length, 0 if the length of stream is unknown
     pub fn body_length(&self) -> usize {
+        self.content_length().unwrap_or(0) as usize
+    }
+
+    /// return the Body length, `None` if the length of stream is unknown
+    pub fn content_length(&self) -> Option<u64> {
         body_kind!(self.kind(),
             text => {
-                text.len()
+                Some(text.len() as u64)
             },
             binary => {
-                binary.len()
+                Some(binary.len() as u64)
             },
             empty => {
-                0
+                Some(0)
+            },
+            stream => {
+                stream.length()
             }
         )
     }
-    /// return http body kind (Empty, Text or Binary)
+    /// return http body kind (Empty, Text, Binary or Stream)
     pub fn kind(&self) -> &BodyKind {
         &self.kind
     }
 
-    // pub fn jsonify<'a,T: Deserialize<'a>>(&self) -> Result<T> {
-    //     match self.kind() {
-    //         BodyKind::Text(text) => {
-    //             serde_json::from_str(text.to_owned().as_str())
-    //         }
-    //         BodyKind::Binary(buf) => {
-    //             let buf = buf.clone();
-    //             serde_json::from_slice(buf.as_ref())
-    //         }
-    //     }
-    // }
+    /// set the `Content-Type` sent with the body, the header set by user takes precedence
+    pub fn with_content_type(mut self, content_type: HeaderValue) -> Self {
+        self.content_type = Some(content_type);
+        self
+    }
+
+    /// the `Content-Type` sent with the body if the request has no `Content-Type` header
+    pub fn content_type(&self) -> Option<&HeaderValue> {
+        self.content_type.as_ref()
+    }
+
+    /// the whole body data.
+    ///
+    /// the stream body is read to the end through the shared reader, so it's consumed
+    /// even though `self` is borrowed, the next read of the body returns no data
+    pub(crate) fn to_vec(&self) -> Result<Vec<u8>> {
+        body_kind!(self.kind(),
+            text => {
+                Ok(text.as_bytes().to_vec())
+            },
+            binary => {
+                Ok(binary.to_vec())
+            },
+            empty => {
+                Ok(Vec::new())
+            },
+            stream => {
+                let mut data = Vec::new();
+                stream.clone().read_to_end(&mut data)?;
+                Ok(data)
+            }
+        )
+    }
+}
+
+impl Response<Body> {
+    /// deserialize the response body as json, the error contains the beginning of body.
+    /// the stream body of `HttpClient::send_streaming` is consumed
+    ///
+    /// ```no_run
+    /// use std::collections::HashMap;
+    /// use request_rs::produce::*;
+    ///
+    /// let mut client = HttpClient::http();
+    /// let resp = client.send_request("http://www.example.com/api/user", Method::GET, None, None).unwrap();
+    /// let user: HashMap<String, String> = resp.json().unwrap();
+    /// ```
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T> {
+        let data = self.body().to_vec()?;
+        serde_json::from_slice(&data).map_err(|e| Error::from(JsonError::new(e, Some(&data))))
+    }
+
+    /// deserialize the `application/x-www-form-urlencoded` response body,
+    /// into a struct or a list of key/value pairs.
+    /// the stream body of `HttpClient::send_streaming` is consumed
+    ///
+    /// ```no_run
+    /// use request_rs::produce::*;
+    ///
+    /// let mut client = HttpClient::http();
+    /// let resp = client.send_request("http://www.example.com/oauth/token", Method::POST, None, None).unwrap();
+    /// let pairs: Vec<(String, String)> = resp.form().unwrap();
+    /// ```
+    pub fn form<T: DeserializeOwned>(&self) -> Result<T> {
+        let data = self.body().to_vec()?;
+        T::deserialize(serde_urlencoded::Deserializer::new(form_urlencoded::parse(&data)))
+            .map_err(|e| Error::from(FormError::new(&format!("failed to decode form: {}", e))))
+    }
+}
+
+/// read the body data, the data which has been read is removed from the body.
+///
+/// the `Stream` body of response returned by `HttpClient::send_streaming` is read from connection
+impl Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let BodyKind::Text(text) = &self.kind {
+            self.kind = BodyKind::Binary(BytesMut::from(text.as_bytes()));
+        }
+        match self.kind {
+            BodyKind::Binary(ref mut bytes) => {
+                let n = std::cmp::min(buf.len(), bytes.len());
+                buf[..n].copy_from_slice(&bytes.split_to(n));
+                Ok(n)
+            }
+            BodyKind::Stream(ref mut stream) => stream.read(buf),
+            _ => Ok(0),
+        }
+    }
 }
 
 /// The Http Request/Response Body Type
@@ -108,4 +260,166 @@ pub enum BodyKind {
     Binary(BytesMut),
     /// the request content is empty
     Empty,
+    /// the request content is read from stream
+    Stream(BodyStream),
+}
+
+/// The body read from `Read`, the stream can only be sent once unless it's read from file,
+/// the clones share the same stream.
+#[derive(Clone)]
+pub struct BodyStream {
+    reader: Arc<Mutex<Option<Box<dyn Read + Send>>>>,
+    length: Option<u64>,
+    trailers: Option<HeaderMap>,
+    /// the file opened again once the reader is taken
+    path: Option<PathBuf>,
+}
+
+impl BodyStream {
+    /// Construct a new BodyStream read from `reader`
+    pub fn new<R: Read + Send + 'static>(reader: R) -> Self {
+        Self {
+            reader: Arc::new(Mutex::new(Some(Box::new(reader)))),
+            length: None,
+            trailers: None,
+            path: None,
+        }
+    }
+
+    /// the stream will yield `length` bytes, it's sent as `Content-Length` instead of chunked encoding
+    pub fn with_length(mut self, length: u64) -> Self {
+        self.length = Some(length);
+        self
+    }
+
+    /// the length of stream, `None` if unknown
+    pub fn length(&self) -> Option<u64> {
+        self.length
+    }
+
+    /// send the trailer fields after the body, the names are announced by `Trailer` header
+    ///
+    /// ```
+    /// use request_rs::produce::*;
+    /// use request_rs::headers::HeaderMap;
+    ///
+    /// let mut trailers = HeaderMap::new();
+    /// trailers.insert("Checksum", "abc123".parse().unwrap());
+    /// let body = Body::new(BodyKind::Stream(BodyStream::new(std::io::empty()).with_trailers(trailers)));
+    /// ```
+    pub fn with_trailers(mut self, trailers: HeaderMap) -> Self {
+        self.trailers = Some(trailers);
+        self
+    }
+
+    /// the trailer fields
+    pub fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()
+    }
+
+    /// the file is opened again for each send after the first one
+    fn reopen_from(mut self, path: PathBuf) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    /// the stream can be sent more than once
+    pub(crate) fn is_replayable(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// take the reader to send the body, `None` if the stream has been sent
+    pub(crate) fn take(&self) -> Option<Box<dyn Read + Send>> {
+        let reader = self.reader.lock().ok().and_then(|mut reader| reader.take());
+        reader.or_else(|| self.reopen())
+    }
+
+    /// open the file of body again
+    fn reopen(&self) -> Option<Box<dyn Read + Send>> {
+        let path = self.path.as_ref()?;
+        match File::open(path) {
+            Ok(file) => Some(Box::new(file)),
+            Err(e) => {
+                debug!("failed to open {} again: {}", path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+impl Read for BodyStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut reader = self.reader.lock()
+            .map_err(|_| io::Error::other("the body stream is poisoned"))?;
+        if reader.is_none() {
+            *reader = self.reopen();
+        }
+        match reader.as_mut() {
+            Some(reader) => reader.read(buf),
+            None => Err(io::Error::other("the body stream has been sent")),
+        }
+    }
+}
+
+impl fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let consumed = self.reader.lock().map(|reader| reader.is_none()).unwrap_or(true);
+        f.debug_struct("BodyStream")
+            .field("consumed", &consumed)
+            .field("length", &self.length)
+            .field("trailers", &self.trailers)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::response::Response;
+
+    use super::{Body, BodyKind};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Login {
+        username: String,
+        remember: bool,
+    }
+
+    fn text(body: &Body) -> &str {
+        match body.kind() {
+            BodyKind::Text(text) => text,
+            _ => panic!("body should be text"),
+        }
+    }
+
+    #[test]
+    fn test_form_body() {
+        let body = Body::form(&[("q", "a b&c=d"), ("lang", "中文")]).unwrap();
+        assert_eq!("q=a+b%26c%3Dd&lang=%E4%B8%AD%E6%96%87", text(&body));
+        assert_eq!("application/x-www-form-urlencoded", body.content_type().unwrap());
+
+        let body = Body::form(&Login { username: "admin".to_owned(), remember: true }).unwrap();
+        assert_eq!("username=admin&remember=true", text(&body));
+
+        assert!(Body::form(&vec![vec![1, 2]]).is_err());
+    }
+
+    #[test]
+    fn test_form_response() {
+        let resp = Response::new(Body::from_str("username=ad%2Bmin&remember=false"));
+        let login: Login = resp.form().unwrap();
+        assert_eq!(Login { username: "ad+min".to_owned(), remember: false }, login);
+
+        let pairs: Vec<(String, String)> = Response::new(Body::from_str("a=1&b=x+y")).form().unwrap();
+        assert_eq!(vec![("a".to_owned(), "1".to_owned()), ("b".to_owned(), "x y".to_owned())], pairs);
+    }
+
+    #[test]
+    fn test_stream_body_consumed() {
+        let resp = Response::new(Body::from_reader(&b"[1, 2]"[..]));
+        assert_eq!(vec![1, 2], resp.json::<Vec<u32>>().unwrap());
+        assert!(resp.body().to_vec().unwrap().is_empty());
+    }
 }
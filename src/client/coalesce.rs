@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use crate::body::{Body, BodyKind};
+use crate::client::decompress::ContentEncoding;
+use crate::client::redirect::RedirectHistory;
+use crate::error::{CoalesceError, Error, Result};
+use crate::header::{ACCEPT, ACCEPT_ENCODING, ACCEPT_LANGUAGE, AUTHORIZATION, COOKIE, HeaderName};
+use crate::method::Method;
+use crate::request::Request;
+use crate::response::Response;
+
+/// Coalesce the identical concurrent requests of the clients sharing it,
+/// only one of them is sent and the others receive a clone of its response or error.
+///
+/// The requests are identical if they have the same safe method (`GET`, `HEAD`, `OPTIONS`, `TRACE`),
+/// URL and values of the vary headers, the requests with body are never coalesced.
+/// The default vary headers are `Accept`, `Accept-Encoding`, `Accept-Language`, `Authorization` and `Cookie`,
+/// the cookies added by cookie store are not part of the key,
+/// so the clients sharing a coalescer should share the cookie store too.
+///
+/// The clones are cheap and share the requests in flight.
+///
+/// ```no_run
+/// use request_rs::produce::*;
+///
+/// let coalescer = RequestCoalescer::new();
+/// let handles: Vec<_> = (0..8).map(|_| {
+///     let coalescer = coalescer.clone();
+///     std::thread::spawn(move || {
+///         let mut client = HttpClient::http();
+///         client.set_request_coalescer(coalescer);
+///         // only one request is sent if they are sent at the same time
+///         client.send_request("http://config.example.com/app.json", Method::GET, None, None)
+///     })
+/// }).collect();
+/// for handle in handles {
+///     println!("{}", handle.join().unwrap().expect("failed").status());
+/// }
+/// ```
+#[derive(Clone)]
+pub struct RequestCoalescer {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    vary: Vec<HeaderName>,
+    flights: Mutex<HashMap<FlightKey, Arc<Flight>>>,
+}
+
+/// the identity of request
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct FlightKey {
+    method: Method,
+    url: String,
+    /// the values of vary headers in order
+    headers: Vec<Vec<Vec<u8>>>,
+}
+
+/// the request in flight, the result is set by the leader once
+struct Flight {
+    result: Mutex<Option<Result<Response<Body>>>>,
+    done: Condvar,
+}
+
+/// the role of the request joining a flight
+pub(crate) enum Join {
+    /// the request should be sent, the result is shared by `Leader::finish`
+    Leader(Leader),
+    /// the identical request is in flight, wait for its result by `Waiter::wait`
+    Waiter(Waiter),
+}
+
+/// the request sent for all identical requests,
+/// the waiters receive an error if it's dropped without result
+pub(crate) struct Leader {
+    coalescer: RequestCoalescer,
+    key: FlightKey,
+    flight: Arc<Flight>,
+}
+
+pub(crate) struct Waiter {
+    flight: Arc<Flight>,
+}
+
+impl RequestCoalescer {
+    /// Create a coalescer with the default vary headers
+    pub fn new() -> Self {
+        Self::with_vary(vec![ACCEPT, ACCEPT_ENCODING, ACCEPT_LANGUAGE, AUTHORIZATION, COOKIE])
+    }
+
+    /// Create a coalescer distinguishing the requests by `vary` headers besides method and URL,
+    /// the headers affecting the response should be listed, such as the headers in `Vary` of response
+    ///
+    /// ```
+    /// use request_rs::produce::*;
+    /// use request_rs::headers::{ACCEPT, AUTHORIZATION, HeaderName};
+    ///
+    /// let coalescer = RequestCoalescer::with_vary(vec![ACCEPT, AUTHORIZATION, HeaderName::from_static("x-tenant")]);
+    /// ```
+    pub fn with_vary(vary: Vec<HeaderName>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                vary,
+                flights: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// the headers distinguishing the requests besides method and URL
+    pub fn vary(&self) -> &[HeaderName] {
+        &self.inner.vary
+    }
+
+    /// the number of requests in flight
+    pub fn in_flight(&self) -> usize {
+        self.flights().len()
+    }
+
+    /// the key of request, `None` if the request can't be coalesced
+    pub(crate) fn key(&self, req: &Request<Body>) -> Option<FlightKey> {
+        if !req.method().is_safe() {
+            return None;
+        }
+        match req.body().kind() {
+            BodyKind::Empty => {}
+            _ => return None,
+        }
+        let url = req.uri()?;
+        let headers = self.inner.vary.iter()
+            .map(|name| req.headers().get_all(name).iter().map(|value| value.as_bytes().to_vec()).collect())
+            .collect();
+        Some(FlightKey {
+            method: req.method().clone(),
+            url: url.as_str().to_owned(),
+            headers,
+        })
+    }
+
+    /// join the flight of `key`, the first request becomes the leader
+    pub(crate) fn join(&self, key: FlightKey) -> Join {
+        let mut flights = self.flights();
+        if let Some(flight) = flights.get(&key) {
+            return Join::Waiter(Waiter { flight: flight.clone() });
+        }
+        let flight = Arc::new(Flight {
+            result: Mutex::new(None),
+            done: Condvar::new(),
+        });
+        flights.insert(key.clone(), flight.clone());
+        Join::Leader(Leader {
+            coalescer: self.clone(),
+            key,
+            flight,
+        })
+    }
+
+    fn flights(&self) -> MutexGuard<'_, HashMap<FlightKey, Arc<Flight>>> {
+        self.inner.flights.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for RequestCoalescer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for RequestCoalescer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestCoalescer")
+            .field("vary", &self.inner.vary)
+            .field("in_flight", &self.in_flight())
+            .finish()
+    }
+}
+
+impl Flight {
+    fn result(&self) -> MutexGuard<'_, Option<Result<Response<Body>>>> {
+        self.result.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Leader {
+    /// share the result with the waiters
+    pub fn finish(self, result: &Result<Response<Body>>) {
+        let shared = match result {
+            Ok(resp) => Ok(clone_response(resp)),
+            Err(e) => Err(e.clone()),
+        };
+        *self.flight.result() = Some(shared);
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        self.coalescer.flights().remove(&self.key);
+        let mut result = self.flight.result();
+        if result.is_none() {
+            *result = Some(Err(Error::from(CoalesceError::new("the coalesced request was abandoned"))));
+        }
+        self.flight.done.notify_all();
+    }
+}
+
+impl Waiter {
+    /// wait until the leader finish, return a clone of its response
+    pub fn wait(self) -> Result<Response<Body>> {
+        let mut result = self.flight.result();
+        while result.is_none() {
+            result = self.flight.done.wait(result).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        match result.as_ref().expect("the result is set") {
+            Ok(resp) => Ok(clone_response(resp)),
+            Err(e) => Err(e.clone()),
+        }
+    }
+}
+
+/// clone the response with the extensions added by client
+fn clone_response(resp: &Response<Body>) -> Response<Body> {
+    let mut clone = Response::new(resp.body().clone());
+    *clone.status_mut() = resp.status();
+    *clone.version_mut() = resp.version();
+    *clone.headers_mut() = resp.headers().clone();
+    if let Some(history) = resp.extensions().get::<RedirectHistory>() {
+        clone.extensions_mut().insert(history.clone());
+    }
+    if let Some(encoding) = resp.extensions().get::<ContentEncoding>() {
+        clone.extensions_mut().insert(encoding.clone());
+    }
+    clone
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::sync::{Arc, mpsc};
+
+    use url::Url;
+
+    use crate::body::Body;
+    use crate::client::tests::{local_client, read_request};
+    use crate::method::Method;
+    use crate::request::Request;
+    use crate::status::StatusCode;
+
+    use super::{Join, RequestCoalescer};
+
+    fn request(method: Method, url: &str, accept: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(Url::parse(url).unwrap())
+            .header("Accept", accept)
+            .body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_key() {
+        let coalescer = RequestCoalescer::new();
+        let key = coalescer.key(&request(Method::GET, "http://example.com/a", "*/*")).unwrap();
+        assert_eq!(Some(key.clone()), coalescer.key(&request(Method::GET, "http://example.com/a", "*/*")));
+        assert_ne!(Some(key.clone()), coalescer.key(&request(Method::GET, "http://example.com/a", "text/html")));
+        assert_ne!(Some(key.clone()), coalescer.key(&request(Method::HEAD, "http://example.com/a", "*/*")));
+        assert_eq!(None, coalescer.key(&request(Method::POST, "http://example.com/a", "*/*")));
+        let mut req = request(Method::GET, "http://example.com/a", "*/*");
+        *req.body_mut() = Body::from_vec(b"query".to_vec());
+        assert_eq!(None, coalescer.key(&req));
+
+        let coalescer = RequestCoalescer::with_vary(Vec::new());
+        assert_eq!(coalescer.key(&request(Method::GET, "http://example.com/a", "*/*")),
+                   coalescer.key(&request(Method::GET, "http://example.com/a", "text/html")));
+    }
+
+    #[test]
+    fn test_abandoned_leader() {
+        let coalescer = RequestCoalescer::new();
+        let key = coalescer.key(&request(Method::GET, "http://example.com/", "*/*")).unwrap();
+        let leader = match coalescer.join(key.clone()) {
+            Join::Leader(leader) => leader,
+            Join::Waiter(_) => panic!("the first request is the leader"),
+        };
+        let waiter = match coalescer.join(key) {
+            Join::Waiter(waiter) => waiter,
+            Join::Leader(_) => panic!("the request is in flight"),
+        };
+        assert_eq!(1, coalescer.in_flight());
+        drop(leader);
+        assert!(waiter.wait().is_err());
+        assert_eq!(0, coalescer.in_flight());
+    }
+
+    /// the number of waiters, the flight is shared by the map, the leader and the waiters
+    fn waiters(coalescer: &RequestCoalescer) -> usize {
+        coalescer.flights().values().map(|flight| Arc::strong_count(flight) - 2).sum()
+    }
+
+    #[test]
+    fn test_concurrent_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/config", listener.local_addr().unwrap());
+        let (received_tx, received) = mpsc::channel();
+        let (respond, respond_rx) = mpsc::channel();
+        // the response is held until all other clients are waiting for it
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&mut stream).unwrap();
+            received_tx.send(()).unwrap();
+            respond_rx.recv().unwrap();
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\nversion").unwrap();
+            listener
+        });
+
+        let coalescer = RequestCoalescer::new();
+        let clients: Vec<_> = (0..8).map(|_| {
+            let coalescer = coalescer.clone();
+            let url = url.clone();
+            std::thread::spawn(move || {
+                let mut client = local_client();
+                client.set_request_coalescer(coalescer);
+                let resp = client.send(request(Method::GET, &url, "*/*")).unwrap();
+                (resp.status(), String::from_utf8(resp.body().to_vec().unwrap()).unwrap())
+            })
+        }).collect();
+        received.recv().unwrap();
+        while waiters(&coalescer) < 7 {
+            std::thread::yield_now();
+        }
+        assert_eq!(1, coalescer.in_flight());
+        respond.send(()).unwrap();
+        for client in clients {
+            assert_eq!((StatusCode::OK, "version".to_owned()), client.join().unwrap());
+        }
+        assert_eq!(0, coalescer.in_flight());
+        // no other connection is made
+        let listener = server.join().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        assert!(listener.accept().is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use std::io::Read;
+#[cfg(any(feature = "gzip", feature = "deflate"))]
+use std::io::Write;
+
+use crate::body::{Body, BodyKind, BodyStream};
+use crate::error::Result;
+use crate::header::{CONTENT_ENCODING, CONTENT_LENGTH, HeaderValue};
+use crate::request::Request;
+
+/// the default minimum size of body to be compressed
+const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// the content coding used to compress request body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Coding {
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "deflate")]
+    Deflate,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+/// compress the request body before sending, the `Content-Encoding` header is set
+/// and `Content-Length` is recomputed. The body smaller than `min_size`,
+/// or the request which already has `Content-Encoding` is sent as it is.
+///
+/// `gzip`, `deflate` and `zstd` are supported by the cargo features with the same names.
+///
+/// ```
+/// use request_rs::produce::*;
+///
+/// let mut client = HttpClient::http();
+/// # #[cfg(feature = "gzip")]
+/// client.set_request_compression(RequestCompression::gzip().min_size(4096));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RequestCompression {
+    coding: Coding,
+    min_size: u64,
+}
+
+impl RequestCompression {
+    fn new(coding: Coding) -> Self {
+        Self {
+            coding,
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+
+    /// compress the body with `gzip`
+    #[cfg(feature = "gzip")]
+    pub fn gzip() -> Self {
+        Self::new(Coding::Gzip)
+    }
+
+    /// compress the body with `deflate`, the data is in zlib format
+    #[cfg(feature = "deflate")]
+    pub fn deflate() -> Self {
+        Self::new(Coding::Deflate)
+    }
+
+    /// compress the body with `zstd`
+    #[cfg(feature = "zstd")]
+    pub fn zstd() -> Self {
+        Self::new(Coding::Zstd)
+    }
+
+    /// the body smaller than `min_size` bytes is not compressed, default is 1024.
+    /// the stream body with unknown length is always compressed, the empty body is never compressed
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size.max(1);
+        self
+    }
+
+    /// the value of `Content-Encoding`
+    fn name(&self) -> &'static str {
+        match self.coding {
+            #[cfg(feature = "gzip")]
+            Coding::Gzip => "gzip",
+            #[cfg(feature = "deflate")]
+            Coding::Deflate => "deflate",
+            #[cfg(feature = "zstd")]
+            Coding::Zstd => "zstd",
+        }
+    }
+
+    /// compress the whole data
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.coding {
+            #[cfg(feature = "gzip")]
+            Coding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "deflate")]
+            Coding::Deflate => {
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "zstd")]
+            Coding::Zstd => Ok(zstd::stream::encode_all(data, 0)?),
+        }
+    }
+
+    /// compress the data read from `reader` while sending
+    fn encode_reader(&self, reader: Box<dyn Read + Send>) -> Result<Box<dyn Read + Send>> {
+        match self.coding {
+            #[cfg(feature = "gzip")]
+            Coding::Gzip => Ok(Box::new(flate2::read::GzEncoder::new(reader, flate2::Compression::default()))),
+            #[cfg(feature = "deflate")]
+            Coding::Deflate => Ok(Box::new(flate2::read::ZlibEncoder::new(reader, flate2::Compression::default()))),
+            #[cfg(feature = "zstd")]
+            Coding::Zstd => Ok(Box::new(zstd::stream::read::Encoder::new(reader, 0)?)),
+        }
+    }
+
+    /// compress the body of `req` in place
+    pub(crate) fn compress(&self, req: &mut Request<Body>) -> Result<()> {
+        if req.headers().contains_key(CONTENT_ENCODING) {
+            return Ok(());
+        }
+        match req.body().content_length() {
+            Some(len) if len < self.min_size => return Ok(()),
+            _ => {}
+        }
+        let body = match req.body().kind() {
+            BodyKind::Text(text) => Body::from_vec(self.encode(text.as_bytes())?),
+            BodyKind::Binary(bytes) => Body::from_vec(self.encode(bytes.as_ref())?),
+            BodyKind::Stream(stream) => {
+                let reader = match stream.take() {
+                    Some(reader) => reader,
+                    None => return Ok(()),
+                };
+                // the compressed length is unknown, the body is sent with chunked encoding
+                let mut compressed = BodyStream::new(self.encode_reader(reader)?);
+                if let Some(trailers) = stream.trailers() {
+                    compressed = compressed.with_trailers(trailers.clone());
+                }
+                Body::new(BodyKind::Stream(compressed))
+            }
+            BodyKind::Empty => return Ok(()),
+        };
+        let body = match req.body().content_type() {
+            Some(content_type) => body.with_content_type(content_type.clone()),
+            None => body,
+        };
+        let headers = req.headers_mut();
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(self.name()));
+        match body.content_length() {
+            Some(len) => {
+                headers.insert(CONTENT_LENGTH, HeaderValue::from(len));
+            }
+            None => {
+                headers.remove(CONTENT_LENGTH);
+            }
+        }
+        *req.body_mut() = body;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use url::Url;
+
+    use crate::body::{Body, BodyKind};
+    use crate::header::{CONTENT_ENCODING, CONTENT_LENGTH};
+    use crate::request::Request;
+
+    use super::RequestCompression;
+
+    fn request(body: Body) -> Request<Body> {
+        Request::builder()
+            .uri(Url::parse("http://example.com/ingest").unwrap())
+            .header(CONTENT_LENGTH, body.body_length())
+            .body(body).unwrap()
+    }
+
+    fn gunzip(data: &[u8]) -> Vec<u8> {
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(data).read_to_end(&mut decoded).unwrap();
+        decoded
+    }
+
+    #[test]
+    fn test_compress_binary() {
+        let data = vec![b'a'; 4096];
+        let mut req = request(Body::from_vec(data.clone()));
+        RequestCompression::gzip().compress(&mut req).unwrap();
+        assert_eq!("gzip", req.headers().get(CONTENT_ENCODING).unwrap());
+        let compressed = match req.body().kind() {
+            BodyKind::Binary(bytes) => bytes.to_vec(),
+            _ => panic!("body should be binary"),
+        };
+        assert!(compressed.len() < data.len());
+        assert_eq!(compressed.len().to_string(), req.headers().get(CONTENT_LENGTH).unwrap().to_str().unwrap());
+        assert_eq!(data, gunzip(&compressed));
+    }
+
+    #[test]
+    fn test_skip_small_or_encoded_body() {
+        let mut req = request(Body::from_str("tiny"));
+        RequestCompression::gzip().compress(&mut req).unwrap();
+        assert!(req.headers().get(CONTENT_ENCODING).is_none());
+        let mut req = request(Body::empty());
+        RequestCompression::gzip().min_size(0).compress(&mut req).unwrap();
+        assert!(req.headers().get(CONTENT_ENCODING).is_none());
+
+        let mut req = request(Body::from_vec(vec![b'a'; 4096]));
+        req.headers_mut().insert(CONTENT_ENCODING, "br".parse().unwrap());
+        RequestCompression::gzip().compress(&mut req).unwrap();
+        assert_eq!("br", req.headers().get(CONTENT_ENCODING).unwrap());
+        assert_eq!("4096", req.headers().get(CONTENT_LENGTH).unwrap());
+    }
+
+    #[test]
+    fn test_compress_stream() {
+        let mut req = request(Body::from_reader(Cursor::new(vec![b'a'; 100])));
+        RequestCompression::gzip().min_size(4096).compress(&mut req).unwrap();
+        assert_eq!("gzip", req.headers().get(CONTENT_ENCODING).unwrap());
+        assert!(req.headers().get(CONTENT_LENGTH).is_none());
+        let mut compressed = Vec::new();
+        req.body_mut().read_to_end(&mut compressed).unwrap();
+        assert_eq!(vec![b'a'; 100], gunzip(&compressed));
+    }
+}
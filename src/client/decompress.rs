@@ -0,0 +1,224 @@
+#[cfg(feature = "deflate")]
+use std::io::BufRead;
+use std::io::{Cursor, Read};
+
+use crate::body::{Body, BodyKind, BodyStream};
+use crate::error::Result;
+use crate::header::{CONTENT_ENCODING, CONTENT_LENGTH, HeaderMap, HeaderValue};
+use crate::method::Method;
+use crate::response::Response;
+use crate::status::StatusCode;
+
+/// the encodings of response body decoded by `HttpClient`,
+/// inserted into the `Extensions` of response
+///
+/// ```no_run
+/// use request_rs::produce::*;
+///
+/// let mut client = HttpClient::http();
+/// let resp = client.send_request("http://www.example.com", Method::GET, None, None).unwrap();
+/// if let Some(encoding) = resp.extensions().get::<ContentEncoding>() {
+///     println!("decoded from {:?}", encoding.encodings());
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentEncoding {
+    encodings: Vec<String>,
+}
+
+impl ContentEncoding {
+    /// the encodings in the order they were applied by server
+    pub fn encodings(&self) -> &[String] {
+        &self.encodings
+    }
+}
+
+/// the value of `Accept-Encoding` header, `None` if no decoder is enabled
+pub(crate) fn accept_encoding() -> Option<HeaderValue> {
+    let mut encodings: Vec<&str> = Vec::new();
+    if cfg!(feature = "gzip") {
+        encodings.push("gzip");
+    }
+    if cfg!(feature = "deflate") {
+        encodings.push("deflate");
+    }
+    if cfg!(feature = "brotli") {
+        encodings.push("br");
+    }
+    if encodings.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&encodings.join(", ")).ok()
+}
+
+/// is the encoding can be decoded
+fn supported(encoding: &str) -> bool {
+    (matches!(encoding, "gzip" | "x-gzip") && cfg!(feature = "gzip"))
+        || (encoding == "deflate" && cfg!(feature = "deflate"))
+        || (encoding == "br" && cfg!(feature = "brotli"))
+}
+
+/// the encodings listed by `Content-Encoding`, `identity` is skipped,
+/// `None` if any of the encodings is not supported
+fn content_encodings(headers: &HeaderMap) -> Option<Vec<String>> {
+    let mut encodings = Vec::new();
+    for value in headers.get_all(CONTENT_ENCODING) {
+        for encoding in value.to_str().ok()?.split(',') {
+            let encoding = encoding.trim().to_ascii_lowercase();
+            if encoding.is_empty() || encoding == "identity" {
+                continue;
+            }
+            if !supported(&encoding) {
+                debug!("unsupported content encoding {}", encoding);
+                return None;
+            }
+            encodings.push(encoding);
+        }
+    }
+    Some(encodings)
+}
+
+/// wrap `reader` with the decoder of `encoding`
+fn decoder(encoding: &str, reader: Box<dyn Read + Send>) -> Result<Box<dyn Read + Send>> {
+    match encoding {
+        #[cfg(feature = "gzip")]
+        "gzip" | "x-gzip" => Ok(Box::new(flate2::read::MultiGzDecoder::new(reader))),
+        #[cfg(feature = "deflate")]
+        "deflate" => {
+            // `deflate` should be zlib format, but some servers send raw deflate data
+            let mut reader = std::io::BufReader::new(reader);
+            let head = reader.fill_buf()?;
+            let zlib = head.len() >= 2 && head[0] & 0x0F == 8 && (u16::from(head[0]) << 8 | u16::from(head[1])) % 31 == 0;
+            if zlib {
+                Ok(Box::new(flate2::read::ZlibDecoder::new(reader)))
+            } else {
+                Ok(Box::new(flate2::read::DeflateDecoder::new(reader)))
+            }
+        }
+        #[cfg(feature = "brotli")]
+        "br" => Ok(Box::new(brotli::Decompressor::new(reader, 4096))),
+        _ => Ok(reader),
+    }
+}
+
+/// decode the response body as `Content-Encoding`, the stacked encodings are decoded in reverse order.
+/// the response is returned as it is if any encoding is not supported
+pub(crate) fn decompress(method: &Method, resp: Response<Body>) -> Result<Response<Body>> {
+    let status = resp.status();
+    if *method == Method::HEAD || status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED {
+        return Ok(resp);
+    }
+    let encodings = match content_encodings(resp.headers()) {
+        Some(encodings) if !encodings.is_empty() => encodings,
+        _ => return Ok(resp),
+    };
+    let (mut parts, body) = resp.into_parts();
+    let decoded = match body.kind() {
+        BodyKind::Stream(stream) => match stream.take() {
+            Some(mut reader) => {
+                for encoding in encodings.iter().rev() {
+                    reader = decoder(encoding, reader)?;
+                }
+                Some(Body::new(BodyKind::Stream(BodyStream::new(reader))))
+            }
+            None => None,
+        },
+        BodyKind::Binary(bytes) if !bytes.is_empty() => {
+            let mut reader: Box<dyn Read + Send> = Box::new(Cursor::new(bytes.to_vec()));
+            for encoding in encodings.iter().rev() {
+                reader = decoder(encoding, reader)?;
+            }
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            Some(Body::from_vec(data))
+        }
+        _ => None,
+    };
+    let body = match decoded {
+        Some(body) => body,
+        None => return Ok(Response::from_parts(parts, body)),
+    };
+    parts.headers.remove(CONTENT_ENCODING);
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.extensions.insert(ContentEncoding { encodings });
+    Ok(Response::from_parts(parts, body))
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(feature = "gzip", feature = "deflate"))]
+    use std::io::Write;
+
+    use crate::body::{Body, BodyKind};
+    use crate::header::{CONTENT_ENCODING, CONTENT_LENGTH};
+    use crate::method::Method;
+    use crate::response::Response;
+
+    #[cfg(feature = "gzip")]
+    use super::ContentEncoding;
+    use super::decompress;
+
+    fn response(encoding: &str, data: Vec<u8>) -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_ENCODING, encoding)
+            .header(CONTENT_LENGTH, data.len())
+            .body(Body::from_vec(data)).unwrap()
+    }
+
+    fn body(resp: &Response<Body>) -> Vec<u8> {
+        match resp.body().kind() {
+            BodyKind::Binary(bytes) => bytes.to_vec(),
+            _ => panic!("body should be binary"),
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_unsupported_encoding() {
+        let resp = decompress(&Method::GET, response("compress", b"data".to_vec())).unwrap();
+        assert_eq!("compress", resp.headers().get(CONTENT_ENCODING).unwrap());
+        assert_eq!(b"data".to_vec(), body(&resp));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        let resp = decompress(&Method::GET, response("gzip", gzip(b"hello gzip"))).unwrap();
+        assert_eq!(b"hello gzip".to_vec(), body(&resp));
+        assert!(resp.headers().get(CONTENT_ENCODING).is_none());
+        assert!(resp.headers().get(CONTENT_LENGTH).is_none());
+        assert_eq!(&["gzip".to_owned()], resp.extensions().get::<ContentEncoding>().unwrap().encodings());
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn test_deflate() {
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(b"hello zlib").unwrap();
+        let resp = decompress(&Method::GET, response("deflate", zlib.finish().unwrap())).unwrap();
+        assert_eq!(b"hello zlib".to_vec(), body(&resp));
+
+        let mut raw = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        raw.write_all(b"hello raw deflate").unwrap();
+        let resp = decompress(&Method::GET, response("deflate", raw.finish().unwrap())).unwrap();
+        assert_eq!(b"hello raw deflate".to_vec(), body(&resp));
+    }
+
+    #[cfg(all(feature = "gzip", feature = "brotli"))]
+    #[test]
+    fn test_stacked_encodings() {
+        let mut br = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+        br.write_all(&gzip(b"hello stacked")).unwrap();
+        let data = br.into_inner();
+        // gzip is applied first
+        let resp = decompress(&Method::GET, response("gzip, br", data)).unwrap();
+        assert_eq!(b"hello stacked".to_vec(), body(&resp));
+        assert_eq!(&["gzip".to_owned(), "br".to_owned()], resp.extensions().get::<ContentEncoding>().unwrap().encodings());
+    }
+}
@@ -0,0 +1,325 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use url::Url;
+
+use crate::body::Body;
+use crate::client::client::HttpClient;
+use crate::client::redirect::host_header;
+use crate::error::{DownloadError, Error, Result};
+use crate::header::{ACCEPT_ENCODING, CONTENT_LENGTH, ETAG, HeaderValue, HOST, IF_RANGE, RANGE, USER_AGENT};
+use crate::method::Method;
+use crate::multipart::ContentRange;
+use crate::proto::Connector;
+use crate::request::Request;
+use crate::response::Response;
+use crate::status::StatusCode;
+
+/// the size of buffer used to copy the body to file
+const COPY_BUFFER_SIZE: usize = 8192;
+/// the suffix of the file which keeps the downloading data
+const PART_SUFFIX: &str = ".part";
+/// the suffix of the file which keeps the `ETag` of partial data
+const ETAG_SUFFIX: &str = ".part.etag";
+
+/// the progress of downloading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    downloaded: u64,
+    total: Option<u64>,
+    elapsed: Duration,
+    received: u64,
+}
+
+impl Progress {
+    /// the bytes have been written to file, including the resumed part
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded
+    }
+
+    /// the size of whole file, `None` if the server doesn't tell
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+
+    /// the average bytes per second received since the download started
+    pub fn rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 { self.received as f64 / secs } else { 0.0 }
+    }
+}
+
+/// the callback reporting the progress of downloading
+type ProgressCallback = Box<dyn FnMut(&Progress)>;
+
+/// the options of `HttpClient::download`
+pub struct DownloadOptions {
+    resume: bool,
+    progress: Option<ProgressCallback>,
+}
+
+impl DownloadOptions {
+    /// Construct the default options, resuming is enabled
+    pub fn new() -> Self {
+        Self {
+            resume: true,
+            progress: None,
+        }
+    }
+
+    /// continue the partial download left by previous call, default is true
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// call `callback` each time the data is written to file
+    pub fn on_progress<F: FnMut(&Progress) + 'static>(mut self, callback: F) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for DownloadOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DownloadOptions")
+            .field("resume", &self.resume)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+/// `path` with `suffix` appended to the file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// the `ETag` which can be used by `If-Range`, weak validator is not allowed
+fn strong_etag<T>(resp: &Response<T>) -> Option<&HeaderValue> {
+    resp.headers().get(ETAG).filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+}
+
+fn content_length<T>(resp: &Response<T>) -> Option<u64> {
+    resp.headers().get(CONTENT_LENGTH)?.to_str().ok()?.trim().parse::<u64>().ok()
+}
+
+impl<C: Connector> HttpClient<C> {
+    /// Download `url` to `path`, return the size of file.
+    ///
+    /// The data is written to `path.part` first and renamed to `path` once completed.
+    /// If `path.part` was left by previous call, only the rest is requested by `Range` header,
+    /// guarded by `If-Range` with the `ETag` of partial data,
+    /// the whole file is downloaded again if it has changed.
+    ///
+    /// ```no_run
+    /// use request_rs::produce::*;
+    ///
+    /// let mut client = HttpClient::http();
+    /// let options = DownloadOptions::new().on_progress(|progress| {
+    ///     println!("{}/{:?} {:.0} B/s", progress.downloaded(), progress.total(), progress.rate());
+    /// });
+    /// client.download("http://www.example.com/large.iso", "large.iso", options).unwrap();
+    /// ```
+    pub fn download<P: AsRef<Path>>(&mut self, url: &str, path: P, mut options: DownloadOptions) -> Result<u64> {
+        let url = Url::parse(url)?;
+        let path = path.as_ref();
+        let part = with_suffix(path, PART_SUFFIX);
+        let etag_path = with_suffix(path, ETAG_SUFFIX);
+
+        let offset = if options.resume {
+            fs::metadata(&part).map(|meta| meta.len()).unwrap_or(0)
+        } else {
+            0
+        };
+        // the partial data can't be validated without `ETag`
+        let etag = if offset > 0 { fs::read_to_string(&etag_path).ok() } else { None };
+
+        let mut req = Request::builder()
+            .method(Method::GET)
+            .header(HOST, host_header(&url)?)
+            .header(USER_AGENT, "request-rs")
+            // the range is the position in encoded data
+            .header(ACCEPT_ENCODING, "identity")
+            .uri(url.clone());
+        if let Some(ref etag) = etag {
+            debug!("resume download of {} from {} bytes", url, offset);
+            req = req.header(RANGE, format!("bytes={}-", offset))
+                .header(IF_RANGE, etag.trim());
+        }
+        let mut resp = self.send_streaming(req.body(Body::empty())?)?;
+
+        let status = resp.status();
+        let (mut file, start, total) = if etag.is_some() && status == StatusCode::PARTIAL_CONTENT {
+            match ContentRange::from_headers(resp.headers()) {
+                Some(range) if range.start() == Some(offset) => {
+                    let file = OpenOptions::new().append(true).open(&part)?;
+                    (file, offset, range.complete_length())
+                }
+                _ => return Err(Error::from(DownloadError::new(&format!(
+                    "unexpected Content-Range when resuming download of {}", url)))),
+            }
+        } else if etag.is_some() && status == StatusCode::RANGE_NOT_SATISFIABLE
+            && ContentRange::from_headers(resp.headers()).and_then(|range| range.complete_length()) == Some(offset) {
+            // the partial data is already completed
+            drop(resp);
+            fs::rename(&part, path)?;
+            let _ = fs::remove_file(&etag_path);
+            return Ok(offset);
+        } else if status.is_success() {
+            let file = File::create(&part)?;
+            match strong_etag(&resp) {
+                Some(etag) => fs::write(&etag_path, etag.as_bytes())?,
+                None => {
+                    if let Err(e) = fs::remove_file(&etag_path) {
+                        if e.kind() != ErrorKind::NotFound {
+                            return Err(Error::from(e));
+                        }
+                    }
+                }
+            }
+            let total = content_length(&resp);
+            (file, 0, total)
+        } else {
+            return Err(Error::from(DownloadError::new(&format!("failed to download {}: {}", url, status))));
+        };
+
+        let started = Instant::now();
+        let mut progress = Progress {
+            downloaded: start,
+            total,
+            elapsed: Duration::from_secs(0),
+            received: 0,
+        };
+        let mut buf = vec![0u8; COPY_BUFFER_SIZE];
+        loop {
+            let n = match resp.body_mut().read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::from(e)),
+            };
+            file.write_all(&buf[..n])?;
+            progress.downloaded += n as u64;
+            progress.received += n as u64;
+            progress.elapsed = started.elapsed();
+            if let Some(ref mut callback) = options.progress {
+                callback(&progress);
+            }
+        }
+        if let Some(total) = total {
+            if progress.downloaded != total {
+                return Err(Error::from(DownloadError::new(&format!(
+                    "downloaded {} bytes of {}, but the file size is {}", progress.downloaded, url, total))));
+            }
+        }
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&part, path)?;
+        let _ = fs::remove_file(&etag_path);
+        Ok(progress.downloaded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+
+    use crate::client::tests::{local_client, serve};
+    use crate::error::DownloadError;
+
+    use super::{DownloadOptions, with_suffix};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("request-rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(with_suffix(&path, ".part"));
+        let _ = fs::remove_file(with_suffix(&path, ".part.etag"));
+        path
+    }
+
+    #[test]
+    fn test_download_with_progress() {
+        let path = temp_path("download");
+        let (url, server) = serve(vec![vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 11\r\nETag: \"v1\"\r\n\r\nhello world",
+        ]]);
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let recorder = reports.clone();
+        let options = DownloadOptions::new().on_progress(move |progress| {
+            recorder.borrow_mut().push((progress.downloaded(), progress.total()));
+        });
+        let mut client = local_client();
+        assert_eq!(11, client.download(url.as_str(), &path, options).unwrap());
+        assert_eq!("hello world", fs::read_to_string(&path).unwrap());
+        assert!(!with_suffix(&path, ".part").exists());
+        assert!(!with_suffix(&path, ".part.etag").exists());
+        assert_eq!(Some(&(11, Some(11))), reports.borrow().last());
+        drop(client);
+        let requests = server.join().unwrap();
+        assert!(!String::from_utf8_lossy(&requests[0]).to_lowercase().contains("range"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_resume_download() {
+        let path = temp_path("resume");
+        fs::write(with_suffix(&path, ".part"), "hello").unwrap();
+        fs::write(with_suffix(&path, ".part.etag"), "\"v1\"").unwrap();
+        let (url, server) = serve(vec![vec![
+            b"HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 5-10/11\r\nContent-Length: 6\r\n\r\n world",
+        ]]);
+        let mut client = local_client();
+        assert_eq!(11, client.download(url.as_str(), &path, DownloadOptions::new()).unwrap());
+        assert_eq!("hello world", fs::read_to_string(&path).unwrap());
+        drop(client);
+        let requests = server.join().unwrap();
+        let req = String::from_utf8_lossy(&requests[0]).to_lowercase();
+        assert!(req.contains("range: bytes=5-\r\n"));
+        assert!(req.contains("if-range: \"v1\"\r\n"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_resume_changed_file() {
+        let path = temp_path("changed");
+        fs::write(with_suffix(&path, ".part"), "stale").unwrap();
+        fs::write(with_suffix(&path, ".part.etag"), "\"v1\"").unwrap();
+        // `If-Range` doesn't match, the whole file is sent
+        let (url, server) = serve(vec![vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 7\r\nETag: \"v2\"\r\n\r\nchanged",
+        ]]);
+        let mut client = local_client();
+        assert_eq!(7, client.download(url.as_str(), &path, DownloadOptions::new()).unwrap());
+        assert_eq!("changed", fs::read_to_string(&path).unwrap());
+        drop(client);
+        server.join().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_download_failed() {
+        let path = temp_path("failed");
+        let (url, server) = serve(vec![vec![b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"]]);
+        let mut client = local_client();
+        let err = client.download(url.as_str(), &path, DownloadOptions::new()).unwrap_err();
+        assert!(err.is::<DownloadError>());
+        assert!(!path.exists());
+        drop(client);
+        server.join().unwrap();
+    }
+}
@@ -1,6 +1,24 @@
 pub use client::HttpClient;
+pub use coalesce::RequestCoalescer;
+#[cfg(any(feature = "gzip", feature = "deflate", feature = "zstd"))]
+pub use compress::RequestCompression;
+pub use decompress::ContentEncoding;
+pub use download::{DownloadOptions, Progress};
+pub use ntlm::NtlmAuth;
+pub use proxy::{NoProxy, Proxy};
+pub use redirect::{RedirectAction, RedirectAttempt, RedirectHistory, RedirectPolicy};
 
 mod client;
+mod coalesce;
+#[cfg(any(feature = "gzip", feature = "deflate", feature = "zstd"))]
+mod compress;
+mod decompress;
+mod download;
+mod ntlm;
+mod pool;
+mod proxy;
+mod redirect;
+mod stream;
 
 // A basic Http request will take the following steps
 // for example we request http://www.example.com:8080/ with GET method
@@ -10,8 +28,9 @@ mod client;
 // then client connect 202.43.78.3:8080 and send a GET Request to host
 // once completed, the client will receive response data from the host
 // finally client close the connection.
+#[cfg(test)]
 #[allow(unused_imports)]
-mod tests {
+pub(crate) mod tests {
     use crate::produce::*;
 
     #[test]
@@ -33,4 +52,755 @@ mod tests {
         let resp = HttpClient::get("http://www.baidu.com", None, None).expect("failed");
         assert_eq!(StatusCode::from_u16(200).expect(""), resp.status());
     }
-}
\ No newline at end of file
+
+    /// read a request head and the body described by `Content-Length`,
+    /// the chunked body is kept as it is on the wire
+    pub(crate) fn read_request<S: std::io::Read>(stream: &mut S) -> Option<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut byte = [0u8; 1];
+        while !data.ends_with(b"\r\n\r\n") {
+            match stream.read(&mut byte) {
+                Ok(1) => data.push(byte[0]),
+                _ => return None,
+            }
+        }
+        let head = String::from_utf8_lossy(&data).to_lowercase();
+        if head.contains("transfer-encoding: chunked") {
+            loop {
+                let line = read_line(stream, &mut data)?;
+                let size = usize::from_str_radix(line.split(';').next()?.trim(), 16).ok()?;
+                if size == 0 {
+                    // the trailers end with an empty line
+                    while !read_line(stream, &mut data)?.is_empty() {}
+                    return Some(data);
+                }
+                let mut chunk = vec![0u8; size + 2];
+                stream.read_exact(&mut chunk).ok()?;
+                data.extend_from_slice(&chunk);
+            }
+        }
+        let length = head.lines()
+            .find(|line| line.starts_with("content-length:"))
+            .and_then(|line| line["content-length:".len()..].trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        let mut body = vec![0u8; length];
+        stream.read_exact(&mut body).ok()?;
+        data.extend_from_slice(&body);
+        Some(data)
+    }
+
+    /// read a line ending with CRLF into `data`, return the line without CRLF
+    fn read_line<S: std::io::Read>(stream: &mut S, data: &mut Vec<u8>) -> Option<String> {
+        let start = data.len();
+        let mut byte = [0u8; 1];
+        while !data[start..].ends_with(b"\r\n") {
+            match stream.read(&mut byte) {
+                Ok(1) => data.push(byte[0]),
+                _ => return None,
+            }
+        }
+        Some(String::from_utf8_lossy(&data[start..data.len() - 2]).into_owned())
+    }
+
+    /// accept connections in order, each connection answers the requests with given responses,
+    /// the last connection is kept open until the client close it.
+    /// return all received requests
+    pub(crate) fn serve(connections: Vec<Vec<&'static [u8]>>) -> (Url, std::thread::JoinHandle<Vec<Vec<u8>>>) {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            let total = connections.len();
+            for (i, responses) in connections.into_iter().enumerate() {
+                let (mut stream, _) = listener.accept().unwrap();
+                for response in responses {
+                    match read_request(&mut stream) {
+                        Some(req) => requests.push(req),
+                        None => break,
+                    }
+                    stream.write_all(response).unwrap();
+                }
+                if i + 1 == total {
+                    // keep the connection open until client close it
+                    while let Ok(size) = stream.read(&mut [0u8; 64]) {
+                        if size == 0 {
+                            break;
+                        }
+                    }
+                }
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    /// serve one connection, write `response` and wait until the client close the connection
+    pub(crate) fn serve_once(response: &'static [u8]) -> (Url, std::thread::JoinHandle<Vec<Vec<u8>>>) {
+        serve(vec![vec![response]])
+    }
+
+    pub(crate) fn local_client() -> HttpClient<crate::proto::HttpConnector> {
+        HttpClient::with_config(crate::config::h1::HttpConfig {
+            connect_timeout: Some(std::time::Duration::from_secs(5)),
+            ..Default::default()
+        })
+    }
+
+    fn get(url: Url) -> Request<Body> {
+        Request::builder()
+            .method(Method::GET)
+            .header("Host", url.host_str().unwrap())
+            .uri(url)
+            .body(Body::empty()).unwrap()
+    }
+
+    fn body_bytes(resp: &Response<Body>) -> Vec<u8> {
+        match resp.body().kind() {
+            BodyKind::Binary(bytes) => bytes.to_vec(),
+            BodyKind::Text(text) => text.as_bytes().to_vec(),
+            BodyKind::Empty => Vec::new(),
+            BodyKind::Stream(_) => {
+                let mut data = Vec::new();
+                std::io::Read::read_to_end(&mut resp.body().clone(), &mut data).unwrap();
+                data
+            }
+        }
+    }
+
+    #[test]
+    fn test_content_length_response_on_kept_alive_connection() {
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello");
+        let mut client = local_client();
+        let resp = client.send(get(url)).expect("request failed");
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!(b"hello".to_vec(), body_bytes(&resp));
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_chunked_response() {
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello\r\n6;name=value\r\n world\r\n0\r\nExpires: never\r\n\r\n");
+        let mut client = local_client();
+        let resp = client.send(get(url)).expect("request failed");
+        assert_eq!(b"hello world".to_vec(), body_bytes(&resp));
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_head_response_has_no_body() {
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 1024\r\n\r\n");
+        let mut client = local_client();
+        let mut req = get(url);
+        *req.method_mut() = Method::HEAD;
+        let resp = client.send(req).expect("request failed");
+        assert_eq!(Vec::<u8>::new(), body_bytes(&resp));
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_read_until_close() {
+        use std::io::Write;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&mut stream).unwrap();
+            stream.write_all(b"HTTP/1.0 200 OK\r\n\r\nuntil close").unwrap();
+        });
+        let mut client = local_client();
+        let resp = client.send(get(url)).expect("request failed");
+        assert_eq!(b"until close".to_vec(), body_bytes(&resp));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_skip_continue_response() {
+        let (url, server) = serve_once(b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 201 Created\r\nContent-Length: 2\r\n\r\nok");
+        let mut client = local_client();
+        let resp = client.send(get(url)).expect("request failed");
+        assert_eq!(StatusCode::CREATED, resp.status());
+        assert_eq!(b"ok".to_vec(), body_bytes(&resp));
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_reuse_kept_alive_connection() {
+        let (url, server) = serve(vec![vec![
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond",
+        ]]);
+        let mut client = local_client();
+        let resp = client.send(get(url.clone())).expect("request failed");
+        assert_eq!(b"first".to_vec(), body_bytes(&resp));
+        // the server only accept one connection
+        let resp = client.send(get(url)).expect("request failed");
+        assert_eq!(b"second".to_vec(), body_bytes(&resp));
+        drop(client);
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn test_connection_close_is_not_reused() {
+        let (url, server) = serve(vec![
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nfirst"],
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond"],
+        ]);
+        let mut client = local_client();
+        let resp = client.send(get(url.clone())).expect("request failed");
+        assert_eq!(b"first".to_vec(), body_bytes(&resp));
+        let resp = client.send(get(url)).expect("request failed");
+        assert_eq!(b"second".to_vec(), body_bytes(&resp));
+        drop(client);
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn test_retry_when_idle_connection_closed_by_server() {
+        // the first connection is closed by server after the response without `Connection: close`
+        let (url, server) = serve(vec![
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst"],
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond"],
+        ]);
+        let mut client = local_client();
+        let resp = client.send(get(url.clone())).expect("request failed");
+        assert_eq!(b"first".to_vec(), body_bytes(&resp));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let resp = client.send(get(url)).expect("request failed");
+        assert_eq!(b"second".to_vec(), body_bytes(&resp));
+        drop(client);
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn test_non_idempotent_request_not_retried() {
+        // the server closes the idle connection, the POST written to it is not sent again
+        let (url, server) = serve(vec![
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst"],
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond"],
+        ]);
+        let mut client = local_client();
+        client.send(get(url.clone())).expect("request failed");
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(client.send(post(url.clone(), "once")).is_err());
+        let resp = client.send(get(url)).expect("request failed");
+        assert_eq!(b"second".to_vec(), body_bytes(&resp));
+        drop(client);
+        let requests = server.join().unwrap();
+        assert_eq!(2, requests.len());
+        assert!(requests[1].starts_with(b"GET / HTTP/1.1\r\n"));
+    }
+
+    /// a connector implementing only the required methods
+    struct PlainConnector(Option<std::net::TcpStream>);
+
+    impl std::io::Read for PlainConnector {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.as_mut().expect("connected").read(buf)
+        }
+    }
+
+    impl std::io::Write for PlainConnector {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.as_mut().expect("connected").write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.as_mut().expect("connected").flush()
+        }
+    }
+
+    impl Connector for PlainConnector {
+        fn create_connection(&mut self, socket_addr: &std::net::SocketAddr) -> Result<std::net::TcpStream> {
+            Ok(std::net::TcpStream::connect(socket_addr)?)
+        }
+
+        fn connect_to(&mut self, addr: &std::net::SocketAddr) -> Result<()> {
+            self.0 = Some(self.create_connection(addr)?);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_connector_without_reuse() {
+        let (url, server) = serve(vec![
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst"],
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond"],
+        ]);
+        let mut client = HttpClient::from_connector(PlainConnector(None));
+        client.send(get(url.clone())).expect("request failed");
+        let resp = client.send(get(url)).expect("request failed");
+        assert_eq!(b"second".to_vec(), body_bytes(&resp));
+        drop(client);
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn test_disable_connection_reuse() {
+        let (url, server) = serve(vec![
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst"],
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond"],
+        ]);
+        let mut client = local_client();
+        client.set_max_idle_per_host(0);
+        client.send(get(url.clone())).expect("request failed");
+        let resp = client.send(get(url)).expect("request failed");
+        assert_eq!(b"second".to_vec(), body_bytes(&resp));
+        drop(client);
+        server.join().unwrap();
+    }
+
+    fn post(url: Url, body: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::POST)
+            .header("Host", url.host_str().unwrap())
+            .header("Content-Length", body.len())
+            .uri(url)
+            .body(Body::from_str(body)).unwrap()
+    }
+
+    fn history(resp: &Response<Body>) -> Vec<String> {
+        resp.extensions().get::<RedirectHistory>().unwrap()
+            .urls().iter()
+            .map(|url| url.path().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_redirect_post_changed_to_get() {
+        let (url, server) = serve(vec![vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /next?a=1\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\ndone",
+        ]]);
+        let mut client = local_client();
+        let resp = client.send(post(url, "name=value")).expect("request failed");
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!(vec!["/", "/next"], history(&resp));
+        drop(client);
+        let requests = server.join().unwrap();
+        let second = String::from_utf8_lossy(&requests[1]).to_lowercase();
+        assert!(second.starts_with("get /next?a=1 http/1.1\r\n"));
+        assert!(!second.contains("content-length"));
+        assert!(second.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_redirect_307_keep_method_and_body() {
+        let (url, server) = serve(vec![vec![
+            b"HTTP/1.1 307 Temporary Redirect\r\nLocation: /next\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\ndone",
+        ]]);
+        let mut client = local_client();
+        let resp = client.send(post(url, "name=value")).expect("request failed");
+        assert_eq!(StatusCode::OK, resp.status());
+        drop(client);
+        let requests = server.join().unwrap();
+        let second = String::from_utf8_lossy(&requests[1]).to_lowercase();
+        assert!(second.starts_with("post /next http/1.1\r\n"));
+        assert!(second.ends_with("\r\n\r\nname=value"));
+    }
+
+    #[test]
+    fn test_redirect_loop() {
+        let (url, server) = serve(vec![vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /a\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.1 302 Found\r\nLocation: /\r\nContent-Length: 0\r\n\r\n",
+        ]]);
+        let mut client = local_client();
+        let err = client.send(get(url)).unwrap_err();
+        assert!(err.is::<crate::error::RedirectError>());
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_redirect_back_after_set_cookie() {
+        let (url, server) = serve(vec![vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /login\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.1 302 Found\r\nLocation: /\r\nSet-Cookie: session=abc\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nhome",
+        ]]);
+        let mut client = local_client();
+        let resp = client.send(get(url)).expect("request failed");
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!(vec!["/", "/login", "/"], history(&resp));
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_redirect_policies() {
+        let (url, server) = serve(vec![vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /a\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.1 302 Found\r\nLocation: /b\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.1 301 Moved Permanently\r\nLocation: /c\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.1 302 Found\r\nLocation: /d\r\nContent-Length: 0\r\n\r\n",
+        ]]);
+        let mut client = local_client();
+        client.set_redirect_policy(RedirectPolicy::None);
+        let resp = client.send(get(url.clone())).expect("request failed");
+        assert_eq!(StatusCode::FOUND, resp.status());
+        assert_eq!(vec!["/"], history(&resp));
+
+        client.set_redirect_policy(RedirectPolicy::Limited(0));
+        assert!(client.send(get(url.clone())).unwrap_err().is::<crate::error::RedirectError>());
+
+        client.set_redirect_policy(RedirectPolicy::custom(|attempt| {
+            if attempt.status() == StatusCode::MOVED_PERMANENTLY {
+                assert_eq!("/c", attempt.url().path());
+                attempt.follow()
+            } else {
+                assert_eq!("/c", attempt.previous().last().unwrap().path());
+                attempt.stop()
+            }
+        }));
+        let resp = client.send(get(url.join("/b").unwrap())).expect("request failed");
+        assert_eq!(StatusCode::FOUND, resp.status());
+        assert_eq!(vec!["/b", "/c"], history(&resp));
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_redirect_cross_origin_strip_credentials() {
+        let (target, target_server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\ndone");
+        let redirect = format!("HTTP/1.1 302 Found\r\nLocation: {}path\r\nContent-Length: 0\r\n\r\n", target);
+        let redirect: &'static [u8] = Box::leak(redirect.into_bytes().into_boxed_slice());
+        let (url, server) = serve_once(redirect);
+
+        let mut client = local_client();
+        let mut req = get(url);
+        req.headers_mut().insert("Authorization", "Basic dXNlcjpwYXNz".parse().unwrap());
+        req.headers_mut().insert("Cookie", "session=secret".parse().unwrap());
+        req.headers_mut().insert("Accept", "text/plain".parse().unwrap());
+        let resp = client.send(req).expect("request failed");
+        assert_eq!(StatusCode::OK, resp.status());
+        drop(client);
+        server.join().unwrap();
+        let requests = target_server.join().unwrap();
+        let req = String::from_utf8_lossy(&requests[0]).to_lowercase();
+        assert!(!req.contains("authorization"));
+        assert!(!req.contains("cookie"));
+        assert!(req.contains("accept: text/plain\r\n"));
+        assert!(req.contains(&format!("host: 127.0.0.1:{}\r\n", target.port().unwrap())));
+    }
+
+    #[test]
+    fn test_cookie_store_login_flow() {
+        use std::sync::{Arc, Mutex};
+        let (url, server) = serve(vec![vec![
+            b"HTTP/1.1 302 Found\r\nLocation: /home\r\nSet-Cookie: session=abc; Path=/; HttpOnly\r\nSet-Cookie: theme=dark; Path=/settings\r\nContent-Length: 0\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nhome",
+            b"HTTP/1.1 200 OK\r\nSet-Cookie: session=; Max-Age=0\r\nContent-Length: 3\r\n\r\nbye",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+        ]]);
+        let jar = Arc::new(Mutex::new(CookieJar::new()));
+        let mut client = local_client();
+        client.set_cookie_store(jar.clone());
+        let mut login = post(url.join("/login").unwrap(), "user=admin");
+        login.headers_mut().insert("Cookie", "lang=en".parse().unwrap());
+        client.send(login).expect("request failed");
+        assert_eq!(2, jar.lock().unwrap().len());
+        client.send(get(url.join("/logout").unwrap())).expect("request failed");
+        assert_eq!(1, jar.lock().unwrap().len());
+        client.send(get(url.join("/settings/a").unwrap())).expect("request failed");
+        drop(client);
+
+        let requests: Vec<String> = server.join().unwrap().iter()
+            .map(|req| String::from_utf8_lossy(req).to_lowercase())
+            .collect();
+        assert!(requests[0].contains("cookie: lang=en\r\n"));
+        assert!(requests[1].contains("cookie: lang=en; session=abc\r\n"));
+        assert!(requests[2].contains("cookie: session=abc\r\n"));
+        assert!(requests[3].contains("cookie: theme=dark\r\n"));
+    }
+
+    #[test]
+    fn test_forward_proxy_absolute_form() {
+        let (mut proxy, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+        proxy.set_username("user").unwrap();
+        proxy.set_password(Some("pass")).unwrap();
+        let mut client = local_client();
+        client.add_proxy(Proxy::http(proxy.as_str()).unwrap());
+        // the proxy is not used for https
+        client.add_proxy(Proxy::https("http://127.0.0.1:1").unwrap());
+        let resp = client.send(get(Url::parse("http://example.com/path?q=1#top").unwrap())).expect("request failed");
+        assert_eq!(b"ok".to_vec(), body_bytes(&resp));
+        drop(client);
+        let requests = server.join().unwrap();
+        let req = String::from_utf8_lossy(&requests[0]);
+        assert!(req.starts_with("GET http://example.com/path?q=1 HTTP/1.1\r\n"));
+        assert!(req.contains("host: example.com\r\n"));
+        assert!(req.contains("proxy-authorization: Basic dXNlcjpwYXNz\r\n"));
+    }
+
+    #[test]
+    fn test_connect_proxy_refused() {
+        let (proxy, server) = serve_once(b"HTTP/1.1 407 Proxy Authentication Required\r\nContent-Length: 0\r\n\r\n");
+        let mut client = local_client();
+        client.add_proxy(Proxy::all(proxy.as_str()).unwrap().basic_auth("user", "pass"));
+        let err = client.send(get(Url::parse("https://example.com/").unwrap())).unwrap_err();
+        assert!(err.is::<crate::error::ProxyError>());
+        drop(client);
+        let requests = server.join().unwrap();
+        assert_eq!(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\
+            Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n".to_vec(), requests[0]);
+    }
+
+    #[test]
+    fn test_chunked_request_body() {
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+        let mut client = local_client();
+        let mut trailers = crate::header::HeaderMap::new();
+        trailers.insert("checksum", "abc".parse().unwrap());
+        let stream = BodyStream::new(std::io::Cursor::new(b"hello world".to_vec())).with_trailers(trailers);
+        let post = |stream: BodyStream| Request::builder()
+            .method(Method::POST)
+            .header("Host", url.host_str().unwrap())
+            .uri(url.clone())
+            .body(Body::new(BodyKind::Stream(stream))).unwrap();
+        let resp = client.send(post(stream.clone())).expect("request failed");
+        assert_eq!(b"ok".to_vec(), body_bytes(&resp));
+        // the stream has been consumed by the first request
+        assert!(client.send(post(stream)).unwrap_err().is::<crate::error::BodyError>());
+        drop(client);
+
+        let requests = server.join().unwrap();
+        let req = String::from_utf8_lossy(&requests[0]).to_lowercase();
+        assert!(req.contains("transfer-encoding: chunked\r\n"));
+        assert!(req.contains("trailer: checksum\r\n"));
+        assert!(!req.contains("content-length"));
+        assert!(req.ends_with("\r\n\r\nb\r\nhello world\r\n0\r\nchecksum: abc\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_stream_request_body_not_sent_on_idle_connection() {
+        // the idle connection is closed by server, the stream body is sent on a new connection
+        let (url, server) = serve(vec![
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst"],
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond"],
+        ]);
+        let mut client = local_client();
+        client.send(get(url.clone())).expect("request failed");
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let req = Request::builder()
+            .method(Method::POST)
+            .header("Host", url.host_str().unwrap())
+            .uri(url)
+            .body(Body::from_reader(std::io::Cursor::new(b"hello".to_vec()))).unwrap();
+        let resp = client.send(req).expect("request failed");
+        assert_eq!(b"second".to_vec(), body_bytes(&resp));
+        drop(client);
+        let requests = server.join().unwrap();
+        assert!(requests[1].ends_with(b"\r\n\r\n5\r\nhello\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_redirect_307_stream_body() {
+        let (url, server) = serve_once(b"HTTP/1.1 307 Temporary Redirect\r\nLocation: /next\r\nContent-Length: 0\r\n\r\n");
+        let mut client = local_client();
+        let req = Request::builder()
+            .method(Method::PUT)
+            .header("Host", url.host_str().unwrap())
+            .uri(url)
+            .body(Body::from_reader(std::io::Cursor::new(b"hello".to_vec()))).unwrap();
+        let err = client.send(req).unwrap_err();
+        assert!(err.is::<crate::error::RedirectError>());
+        drop(client);
+        assert_eq!(1, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn test_stream_request_body_with_content_length() {
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+        let mut client = local_client();
+        let req = Request::builder()
+            .method(Method::PUT)
+            .header("Host", url.host_str().unwrap())
+            .header("Content-Length", 5)
+            .uri(url)
+            .body(Body::from_reader(std::io::Cursor::new(b"hello world".to_vec()))).unwrap();
+        client.send(req).expect("request failed");
+        drop(client);
+        let requests = server.join().unwrap();
+        let req = String::from_utf8_lossy(&requests[0]).to_lowercase();
+        assert!(!req.contains("transfer-encoding"));
+        assert!(req.contains("content-length: 5\r\n"));
+        assert!(req.ends_with("\r\n\r\nhello"));
+    }
+
+    #[test]
+    fn test_file_request_body() {
+        let path = std::env::temp_dir().join(format!("request-rs-upload-{}", std::process::id()));
+        std::fs::write(&path, vec![b'x'; 20000]).unwrap();
+        let body = Body::from_file(&path).unwrap();
+        assert_eq!(Some(20000), body.content_length());
+
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+        let mut client = local_client();
+        let req = Request::builder()
+            .method(Method::PUT)
+            .header("Host", url.host_str().unwrap())
+            .uri(url)
+            .body(body).unwrap();
+        client.send(req).expect("request failed");
+        drop(client);
+        std::fs::remove_file(&path).unwrap();
+        let requests = server.join().unwrap();
+        let req = String::from_utf8_lossy(&requests[0]).to_lowercase();
+        assert!(req.contains("content-length: 20000\r\n"));
+        assert!(!req.contains("transfer-encoding"));
+        assert!(req.ends_with(&format!("\r\n\r\n{}", "x".repeat(20000))));
+    }
+
+    #[test]
+    fn test_file_request_body_resent() {
+        let path = std::env::temp_dir().join(format!("request-rs-resend-{}", std::process::id()));
+        std::fs::write(&path, b"file content").unwrap();
+        // the file is sent again on the idle connection closed by server, then to follow 307
+        let (url, server) = serve(vec![
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"],
+            vec![
+                b"HTTP/1.1 307 Temporary Redirect\r\nLocation: /next\r\nContent-Length: 0\r\n\r\n",
+                b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+            ],
+        ]);
+        let mut client = local_client();
+        client.send(get(url.clone())).expect("request failed");
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let req = Request::builder()
+            .method(Method::PUT)
+            .header("Host", url.host_str().unwrap())
+            .uri(url)
+            .body(Body::from_file(&path).unwrap()).unwrap();
+        let resp = client.send(req).expect("request failed");
+        assert_eq!(vec!["/", "/next"], history(&resp));
+        drop(client);
+        std::fs::remove_file(&path).unwrap();
+        let requests = server.join().unwrap();
+        assert_eq!(3, requests.len());
+        assert!(requests[1].ends_with(b"\r\n\r\nfile content"));
+        assert!(requests[2].starts_with(b"PUT /next HTTP/1.1\r\n"));
+        assert!(requests[2].ends_with(b"\r\n\r\nfile content"));
+    }
+
+    #[test]
+    fn test_streaming_response_reuse_connection() {
+        let (url, server) = serve(vec![vec![
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nfirst\r\n7\r\n second\r\n0\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nthird",
+        ]]);
+        let mut client = local_client();
+        let mut resp = client.send_streaming(get(url.clone())).expect("request failed");
+        match resp.body().kind() {
+            BodyKind::Stream(_) => {}
+            _ => panic!("body should be stream"),
+        }
+        let mut body = String::new();
+        std::io::Read::read_to_string(resp.body_mut(), &mut body).unwrap();
+        assert_eq!("first second", body);
+        // the server only accept one connection
+        let resp = client.send_streaming(get(url)).expect("request failed");
+        assert_eq!(b"third".to_vec(), body_bytes(&resp));
+        drop(client);
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn test_streaming_response_dropped_early() {
+        let (url, server) = serve(vec![
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n0123456789"],
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond"],
+        ]);
+        let mut client = local_client();
+        let mut resp = client.send_streaming(get(url.clone())).expect("request failed");
+        let mut head = [0u8; 4];
+        std::io::Read::read_exact(resp.body_mut(), &mut head).unwrap();
+        assert_eq!(b"0123", &head);
+        // the connection with unread body is closed
+        drop(resp);
+        let resp = client.send(get(url)).expect("request failed");
+        assert_eq!(b"second".to_vec(), body_bytes(&resp));
+        drop(client);
+        assert_eq!(2, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn test_streaming_response_incomplete() {
+        let (url, server) = serve(vec![
+            vec![b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\nshort"],
+            vec![],
+        ]);
+        let mut client = local_client();
+        let mut resp = client.send_streaming(get(url.clone())).expect("request failed");
+        let mut body = Vec::new();
+        let err = std::io::Read::read_to_end(resp.body_mut(), &mut body).unwrap_err();
+        assert_eq!(std::io::ErrorKind::UnexpectedEof, err.kind());
+        assert_eq!(b"short".to_vec(), body);
+        // the server is waiting for the second connection
+        std::net::TcpStream::connect(url.socket_addrs(|| None).unwrap()[0]).unwrap();
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_response_decoded() {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"hello gzip").unwrap();
+        let data = encoder.finish().unwrap();
+        let mut response = format!("HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n", data.len()).into_bytes();
+        response.extend_from_slice(&data);
+        let response: &'static [u8] = Box::leak(response.into_boxed_slice());
+        let (url, server) = serve(vec![vec![response, response]]);
+
+        let mut client = local_client();
+        let resp = client.send(get(url.clone())).expect("request failed");
+        assert_eq!(b"hello gzip".to_vec(), body_bytes(&resp));
+        assert!(resp.headers().get("content-encoding").is_none());
+        assert_eq!(&["gzip".to_owned()], resp.extensions().get::<ContentEncoding>().unwrap().encodings());
+        let resp = client.send_streaming(get(url)).expect("request failed");
+        assert_eq!(b"hello gzip".to_vec(), body_bytes(&resp));
+        drop(client);
+
+        let requests = server.join().unwrap();
+        assert!(String::from_utf8_lossy(&requests[0]).contains("accept-encoding: gzip"));
+    }
+
+    #[test]
+    fn test_json_request_and_response() {
+        #[derive(serde::Serialize)]
+        struct Login<'a> {
+            username: &'a str,
+        }
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Token {
+            token: String,
+        }
+        let (url, server) = serve(vec![vec![
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 15\r\n\r\n{\"token\":\"abc\"}",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 17\r\n\r\n<html>oops</html>",
+        ]]);
+        let mut client = local_client();
+        let resp = client.post_json(url.as_str(), &Login { username: "admin" }).expect("request failed");
+        assert_eq!(Token { token: "abc".to_owned() }, resp.json::<Token>().unwrap());
+
+        let err = client.get_json::<Token>(url.as_str()).unwrap_err();
+        assert!(err.is::<crate::error::JsonError>());
+        assert!(err.to_string().ends_with("body: <html>oops</html>"));
+        drop(client);
+
+        let requests = server.join().unwrap();
+        let req = String::from_utf8_lossy(&requests[0]).to_lowercase();
+        assert!(req.starts_with("post / http/1.1\r\n"));
+        assert!(req.contains("content-type: application/json\r\n"));
+        assert!(req.contains("accept: application/json\r\n"));
+        assert!(req.ends_with("\r\n\r\n{\"username\":\"admin\"}"));
+    }
+}
@@ -0,0 +1,456 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use hmac::{Hmac, Mac};
+use md4::{Digest, Md4};
+use md5::Md5;
+use rand::RngCore;
+
+use crate::body::Body;
+use crate::error::{Error, NtlmError, Result};
+use crate::header::{HeaderValue, WWW_AUTHENTICATE};
+use crate::response::Response;
+use crate::status::StatusCode;
+
+const SIGNATURE: &[u8] = b"NTLMSSP\0";
+const NEGOTIATE_MESSAGE: u32 = 1;
+const CHALLENGE_MESSAGE: u32 = 2;
+const AUTHENTICATE_MESSAGE: u32 = 3;
+
+/// the negotiate flags, MS-NLMP 2.2.2.5
+const NEGOTIATE_UNICODE: u32 = 0x0000_0001;
+const NEGOTIATE_OEM: u32 = 0x0000_0002;
+const REQUEST_TARGET: u32 = 0x0000_0004;
+const NEGOTIATE_NTLM: u32 = 0x0000_0200;
+const NEGOTIATE_ALWAYS_SIGN: u32 = 0x0000_8000;
+const NEGOTIATE_EXTENDED_SESSIONSECURITY: u32 = 0x0008_0000;
+const NEGOTIATE_TARGET_INFO: u32 = 0x0080_0000;
+const NEGOTIATE_128: u32 = 0x2000_0000;
+const NEGOTIATE_56: u32 = 0x8000_0000;
+const NEGOTIATE_FLAGS: u32 = NEGOTIATE_UNICODE | NEGOTIATE_OEM | REQUEST_TARGET | NEGOTIATE_NTLM | NEGOTIATE_ALWAYS_SIGN
+    | NEGOTIATE_EXTENDED_SESSIONSECURITY | NEGOTIATE_128 | NEGOTIATE_56;
+
+/// the id of `AV_PAIR` holding the time of server
+const MSV_AV_EOL: u16 = 0;
+const MSV_AV_TIMESTAMP: u16 = 7;
+
+/// the seconds between 1601-01-01 and 1970-01-01
+const FILETIME_UNIX_EPOCH: u64 = 11_644_473_600;
+
+type HmacMd5 = Hmac<Md5>;
+
+/// The credentials of NTLM authentication, the NTLMv2 responses are computed from them.
+///
+/// The client answers `401 Unauthorized` with `WWW-Authenticate: NTLM` by sending the negotiate and
+/// authenticate messages on the same connection, so the connection must be kept alive during the handshake.
+///
+/// ```
+/// use request_rs::produce::*;
+///
+/// let mut client = HttpClient::http();
+/// client.set_ntlm_auth(NtlmAuth::new("CORP\\alice", "password").workstation("BUILD01"));
+/// ```
+#[derive(Clone)]
+pub struct NtlmAuth {
+    username: String,
+    password: String,
+    domain: String,
+    workstation: String,
+}
+
+/// the `CHALLENGE_MESSAGE` sent by server
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Challenge {
+    flags: u32,
+    server_challenge: [u8; 8],
+    target_info: Vec<u8>,
+}
+
+impl NtlmAuth {
+    /// the domain can be given as `DOMAIN\user`
+    pub fn new(username: &str, password: &str) -> Self {
+        let (domain, username) = match username.find('\\') {
+            Some(index) => (&username[..index], &username[index + 1..]),
+            None => ("", username),
+        };
+        Self {
+            username: username.to_owned(),
+            password: password.to_owned(),
+            domain: domain.to_owned(),
+            workstation: String::new(),
+        }
+    }
+
+    /// Set the domain of user
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = domain.to_owned();
+        self
+    }
+
+    /// Set the name of client machine sent to server, default is empty
+    pub fn workstation(mut self, workstation: &str) -> Self {
+        self.workstation = workstation.to_owned();
+        self
+    }
+
+    /// the `NEGOTIATE_MESSAGE`, the domain and workstation are not supplied
+    pub(crate) fn negotiate(&self) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(32);
+        msg.extend_from_slice(SIGNATURE);
+        msg.extend_from_slice(&NEGOTIATE_MESSAGE.to_le_bytes());
+        msg.extend_from_slice(&NEGOTIATE_FLAGS.to_le_bytes());
+        // empty domain and workstation fields
+        msg.extend_from_slice(&[0u8; 16]);
+        msg
+    }
+
+    /// the `AUTHENTICATE_MESSAGE` answering `challenge` with NTLMv2 response,
+    /// `timestamp` is in FILETIME, it's replaced by the time of server if the challenge has one.
+    ///
+    /// return `NtlmError` if a field is too long for the message
+    pub(crate) fn authenticate(&self, challenge: &Challenge, client_challenge: [u8; 8], timestamp: u64) -> Result<Vec<u8>> {
+        let key = self.ntowf_v2();
+        let server_timestamp = challenge.timestamp();
+        let timestamp = server_timestamp.unwrap_or(timestamp);
+
+        let mut blob = vec![0x01, 0x01, 0, 0, 0, 0, 0, 0];
+        blob.extend_from_slice(&timestamp.to_le_bytes());
+        blob.extend_from_slice(&client_challenge);
+        blob.extend_from_slice(&[0u8; 4]);
+        blob.extend_from_slice(&challenge.target_info);
+        blob.extend_from_slice(&[0u8; 4]);
+        let mut nt_response = hmac_md5(&key, &[&challenge.server_challenge, &blob]).to_vec();
+        nt_response.extend_from_slice(&blob);
+
+        // the LMv2 response is omitted if the server sends its time
+        let lm_response = match server_timestamp {
+            Some(_) => vec![0u8; 24],
+            None => {
+                let mut lm = hmac_md5(&key, &[&challenge.server_challenge, &client_challenge]).to_vec();
+                lm.extend_from_slice(&client_challenge);
+                lm
+            }
+        };
+
+        let unicode = challenge.flags & NEGOTIATE_UNICODE != 0;
+        let encode = |s: &str| if unicode { utf16le(s) } else { s.as_bytes().to_vec() };
+        let flags = challenge.flags & NEGOTIATE_FLAGS & !(if unicode { NEGOTIATE_OEM } else { NEGOTIATE_UNICODE });
+        let fields = [lm_response, nt_response, encode(&self.domain), encode(&self.username), encode(&self.workstation), Vec::new()];
+
+        // the header is followed by the payload of all fields
+        let mut offset = 64;
+        let mut msg = Vec::with_capacity(offset + fields.iter().map(Vec::len).sum::<usize>());
+        msg.extend_from_slice(SIGNATURE);
+        msg.extend_from_slice(&AUTHENTICATE_MESSAGE.to_le_bytes());
+        for field in fields.iter() {
+            let too_long = |_| Error::from(NtlmError::new("the field is too long for NTLM authenticate message"));
+            let len = u16::try_from(field.len()).map_err(too_long)?;
+            msg.extend_from_slice(&len.to_le_bytes());
+            msg.extend_from_slice(&len.to_le_bytes());
+            msg.extend_from_slice(&u32::try_from(offset).map_err(too_long)?.to_le_bytes());
+            offset += field.len();
+        }
+        msg.extend_from_slice(&flags.to_le_bytes());
+        for field in fields.iter() {
+            msg.extend_from_slice(field);
+        }
+        Ok(msg)
+    }
+
+    /// the value of `Authorization` starting the handshake
+    pub(crate) fn negotiate_header(&self) -> HeaderValue {
+        authorization(&self.negotiate())
+    }
+
+    /// the value of `Authorization` answering the challenge of `resp`,
+    /// `None` if the response doesn't carry a challenge
+    pub(crate) fn authenticate_header(&self, resp: &Response<Body>) -> Result<Option<HeaderValue>> {
+        let challenge = match challenge_of(resp) {
+            Some(challenge) => Challenge::parse(&challenge?)?,
+            None => return Ok(None),
+        };
+        let mut client_challenge = [0u8; 8];
+        rand::rng().fill_bytes(&mut client_challenge);
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let timestamp = (since_epoch.as_secs() + FILETIME_UNIX_EPOCH) * 10_000_000 + u64::from(since_epoch.subsec_nanos() / 100);
+        Ok(Some(authorization(&self.authenticate(&challenge, client_challenge, timestamp)?)))
+    }
+
+    /// `NTOWFv2`, the HMAC-MD5 of uppercase user and domain keyed by the MD4 of password
+    fn ntowf_v2(&self) -> [u8; 16] {
+        let nt_hash = Md4::digest(utf16le(&self.password));
+        let identity = utf16le(&format!("{}{}", self.username.to_uppercase(), self.domain));
+        hmac_md5(&nt_hash, &[&identity])
+    }
+}
+
+impl fmt::Debug for NtlmAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NtlmAuth")
+            .field("username", &self.username)
+            .field("domain", &self.domain)
+            .field("workstation", &self.workstation)
+            .finish()
+    }
+}
+
+impl Challenge {
+    /// parse the `CHALLENGE_MESSAGE`
+    pub fn parse(msg: &[u8]) -> Result<Self> {
+        if msg.len() < 32 || &msg[..8] != SIGNATURE || read_u32(msg, 8) != CHALLENGE_MESSAGE {
+            return Err(Error::from(NtlmError::new("invalid NTLM challenge message")));
+        }
+        let flags = read_u32(msg, 20);
+        let mut server_challenge = [0u8; 8];
+        server_challenge.copy_from_slice(&msg[24..32]);
+        let target_info = if flags & NEGOTIATE_TARGET_INFO != 0 && msg.len() >= 48 {
+            let len = read_u16(msg, 40) as usize;
+            let offset = read_u32(msg, 44) as usize;
+            msg.get(offset..offset + len)
+                .ok_or_else(|| Error::from(NtlmError::new("the target info exceeds the NTLM challenge message")))?
+                .to_vec()
+        } else {
+            Vec::new()
+        };
+        Ok(Self { flags, server_challenge, target_info })
+    }
+
+    /// the `MsvAvTimestamp` in target info
+    fn timestamp(&self) -> Option<u64> {
+        let mut info = &self.target_info[..];
+        while info.len() >= 4 {
+            let id = read_u16(info, 0);
+            let len = read_u16(info, 2) as usize;
+            let value = info.get(4..4 + len)?;
+            match id {
+                MSV_AV_EOL => return None,
+                MSV_AV_TIMESTAMP if len == 8 => return Some(read_u64(value)),
+                _ => info = &info[4 + len..],
+            }
+        }
+        None
+    }
+}
+
+/// the response asks for NTLM authentication
+pub(crate) fn is_requested(resp: &Response<Body>) -> bool {
+    resp.status() == StatusCode::UNAUTHORIZED && resp.headers().get_all(WWW_AUTHENTICATE).iter()
+        .any(|value| value.as_bytes().eq_ignore_ascii_case(b"NTLM"))
+}
+
+/// the decoded challenge in `WWW-Authenticate: NTLM <base64>`
+fn challenge_of(resp: &Response<Body>) -> Option<Result<Vec<u8>>> {
+    if resp.status() != StatusCode::UNAUTHORIZED {
+        return None;
+    }
+    resp.headers().get_all(WWW_AUTHENTICATE).iter()
+        .filter_map(|value| value.to_str().ok())
+        .find(|value| value.len() > 5 && value[..5].eq_ignore_ascii_case("NTLM "))
+        .map(|value| STANDARD.decode(value[5..].trim())
+            .map_err(|e| Error::from(NtlmError::new(&format!("invalid NTLM challenge: {}", e)))))
+}
+
+fn authorization(msg: &[u8]) -> HeaderValue {
+    HeaderValue::from_str(&format!("NTLM {}", STANDARD.encode(msg))).expect("base64 is valid header value")
+}
+
+fn hmac_md5(key: &[u8], data: &[&[u8]]) -> [u8; 16] {
+    let mut mac = HmacMd5::new_from_slice(key).expect("HMAC accepts any key size");
+    for part in data {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+fn utf16le(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn read_u64(data: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+
+    use crate::client::tests::{local_client, read_request};
+    use crate::error::NtlmError;
+    use crate::method::Method;
+    use crate::status::StatusCode;
+
+    use super::{Challenge, hmac_md5, NEGOTIATE_FLAGS, NEGOTIATE_TARGET_INFO, NtlmAuth, read_u16, read_u32, utf16le};
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    /// the target info of MS-NLMP 4.2.4, `Domain` and `Server`
+    fn target_info() -> Vec<u8> {
+        let mut info = vec![0x02, 0x00, 0x0c, 0x00];
+        info.extend_from_slice(&utf16le("Domain"));
+        info.extend_from_slice(&[0x01, 0x00, 0x0c, 0x00]);
+        info.extend_from_slice(&utf16le("Server"));
+        info.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+        info
+    }
+
+    fn challenge_message(server_challenge: &[u8]) -> Vec<u8> {
+        let info = target_info();
+        let mut msg = b"NTLMSSP\0".to_vec();
+        msg.extend_from_slice(&2u32.to_le_bytes());
+        // empty target name
+        msg.extend_from_slice(&[0, 0, 0, 0, 48, 0, 0, 0]);
+        msg.extend_from_slice(&(NEGOTIATE_FLAGS | NEGOTIATE_TARGET_INFO).to_le_bytes());
+        msg.extend_from_slice(server_challenge);
+        msg.extend_from_slice(&[0u8; 8]);
+        msg.extend_from_slice(&(info.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&(info.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&48u32.to_le_bytes());
+        msg.extend_from_slice(&info);
+        msg
+    }
+
+    /// the field of `AUTHENTICATE_MESSAGE` at `offset` of header
+    fn field(msg: &[u8], offset: usize) -> &[u8] {
+        let len = read_u16(msg, offset) as usize;
+        let start = read_u32(msg, offset + 4) as usize;
+        &msg[start..start + len]
+    }
+
+    #[test]
+    fn test_ntlm_v2_vectors() {
+        // MS-NLMP 4.2.4
+        let auth = NtlmAuth::new("Domain\\User", "Password").workstation("COMPUTER");
+        assert_eq!(hex("0c868a403bfd7a93a3001ef22ef02e3f"), auth.ntowf_v2());
+
+        let challenge = Challenge::parse(&challenge_message(&hex("0123456789abcdef"))).unwrap();
+        assert_eq!(target_info(), challenge.target_info);
+        let msg = auth.authenticate(&challenge, [0xaa; 8], 0).unwrap();
+        assert_eq!(b"NTLMSSP\0", &msg[..8]);
+        assert_eq!(3, read_u32(&msg, 8));
+        assert_eq!(hex("86c35097ac9cec102554764a57cccc19 aaaaaaaaaaaaaaaa"), field(&msg, 12));
+        assert_eq!(hex("68cd0ab851e51c96aabc927bebef6a1c"), &field(&msg, 20)[..16]);
+        assert_eq!(&utf16le("Domain")[..], field(&msg, 28));
+        assert_eq!(&utf16le("User")[..], field(&msg, 36));
+        assert_eq!(&utf16le("COMPUTER")[..], field(&msg, 44));
+    }
+
+    #[test]
+    fn test_server_timestamp() {
+        let mut msg = challenge_message(&[1; 8]);
+        // replace the EOL with `MsvAvTimestamp` and EOL
+        let info_len = target_info().len() + 12;
+        msg.truncate(msg.len() - 4);
+        msg.extend_from_slice(&[0x07, 0x00, 0x08, 0x00, 1, 2, 3, 4, 5, 6, 7, 8, 0, 0, 0, 0]);
+        msg[40..42].copy_from_slice(&(info_len as u16).to_le_bytes());
+        msg[42..44].copy_from_slice(&(info_len as u16).to_le_bytes());
+        let challenge = Challenge::parse(&msg).unwrap();
+        assert_eq!(Some(0x0807060504030201), challenge.timestamp());
+        let msg = NtlmAuth::new("user", "password").authenticate(&challenge, [2; 8], 42).unwrap();
+        assert_eq!(&[0u8; 24], field(&msg, 12));
+        assert_eq!(&[1, 2, 3, 4, 5, 6, 7, 8], &field(&msg, 20)[24..32]);
+
+        // the user name of 40000 characters is 80000 bytes in UTF-16
+        let user = "u".repeat(40000);
+        let err = NtlmAuth::new(&user, "password").authenticate(&challenge, [2; 8], 42).unwrap_err();
+        assert!(err.is::<NtlmError>());
+
+        assert!(Challenge::parse(b"NTLMSSP\0\x01\x00\x00\x00").is_err());
+    }
+
+    #[test]
+    fn test_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/intranet", listener.local_addr().unwrap());
+        let server_challenge = [7u8; 8];
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut requests = Vec::new();
+            let authorization = |req: &[u8]| String::from_utf8_lossy(req).lines()
+                .find(|line| line.to_lowercase().starts_with("authorization: ntlm "))
+                .map(|line| STANDARD.decode(&line["authorization: ntlm ".len()..]).unwrap());
+
+            let req = read_request(&mut stream).unwrap();
+            assert!(authorization(&req).is_none());
+            stream.write_all(b"HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Negotiate\r\nWWW-Authenticate: NTLM\r\nContent-Length: 0\r\n\r\n").unwrap();
+            requests.push(req);
+
+            let req = read_request(&mut stream).unwrap();
+            assert_eq!(NtlmAuth::new("user", "").negotiate(), authorization(&req).unwrap());
+            let challenge = STANDARD.encode(challenge_message(&server_challenge));
+            stream.write_all(format!("HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: NTLM {}\r\nContent-Length: 0\r\n\r\n", challenge).as_bytes()).unwrap();
+            requests.push(req);
+
+            // verify the NTLMv2 response as the domain controller does
+            let req = read_request(&mut stream).unwrap();
+            let msg = authorization(&req).unwrap();
+            let nt_response = field(&msg, 20);
+            let key = NtlmAuth::new("CORP\\alice", "s3cret").ntowf_v2();
+            let proof = hmac_md5(&key, &[&server_challenge, &nt_response[16..]]);
+            let response: &[u8] = if proof == nt_response[..16] && field(&msg, 36) == &utf16le("alice")[..] {
+                b"HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\nwelcome"
+            } else {
+                b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n"
+            };
+            stream.write_all(response).unwrap();
+            requests.push(req);
+            requests
+        });
+
+        let mut client = local_client();
+        client.set_ntlm_auth(NtlmAuth::new("CORP\\alice", "s3cret"));
+        let resp = client.send_request(&url, Method::POST, None, Some(crate::body::Body::from_vec(b"data".to_vec()))).unwrap();
+        assert_eq!(StatusCode::OK, resp.status());
+        let requests = handle.join().unwrap();
+        // only the last request carries the body
+        assert!(requests[0].ends_with(b"data"));
+        assert!(!requests[1].ends_with(b"data"));
+        assert!(requests[2].ends_with(b"data"));
+    }
+
+    #[test]
+    fn test_handshake_without_idle_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/intranet", listener.local_addr().unwrap());
+        // the first connection is closed by client, the handshake is done on the second one
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&mut stream).unwrap();
+            stream.write_all(b"HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: NTLM\r\nContent-Length: 0\r\n\r\n").unwrap();
+
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&mut stream).unwrap();
+            let challenge = STANDARD.encode(challenge_message(&[7u8; 8]));
+            stream.write_all(format!("HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: NTLM {}\r\nContent-Length: 0\r\n\r\n", challenge).as_bytes()).unwrap();
+            let req = read_request(&mut stream).unwrap();
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\nwelcome").unwrap();
+            String::from_utf8(req).unwrap().to_lowercase().contains("authorization: ntlm ")
+        });
+
+        let mut client = local_client();
+        client.set_max_idle_per_host(0);
+        client.set_ntlm_auth(NtlmAuth::new("CORP\\alice", "s3cret"));
+        let resp = client.send_request(&url, Method::GET, None, None).unwrap();
+        assert_eq!(StatusCode::OK, resp.status());
+        assert!(handle.join().unwrap());
+    }
+}
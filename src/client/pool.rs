@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use url::Url;
+
+use crate::error::{Error, InvalidUrl, Result};
+
+/// the default time of idle connection can be kept in pool
+pub(crate) const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// the default number of idle connections can be kept for each host
+pub(crate) const DEFAULT_MAX_IDLE_PER_HOST: usize = 8;
+
+/// the connections can be reused only if they have the same scheme, host, port and proxy
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct PoolKey {
+    scheme: String,
+    host: String,
+    port: u16,
+    proxy: Option<String>,
+}
+
+impl PoolKey {
+    pub fn from_url(url: &Url) -> Result<Self> {
+        let host = url.host_str().ok_or(Error::from(InvalidUrl::new("missing host")))?;
+        let port = url.port_or_known_default().ok_or(Error::from(InvalidUrl::new("missing port")))?;
+        Ok(Self {
+            scheme: url.scheme().to_owned(),
+            host: host.to_owned(),
+            port,
+            proxy: None,
+        })
+    }
+
+    /// the connection is opened through the proxy
+    pub fn via(mut self, proxy: &Url) -> Self {
+        self.proxy = Some(proxy.as_str().to_owned());
+        self
+    }
+}
+
+struct Idle<T> {
+    stream: T,
+    idle_at: Instant,
+}
+
+/// the idle connections which can be reused by next request
+pub(crate) struct Pool<T> {
+    idle: HashMap<PoolKey, Vec<Idle<T>>>,
+    idle_timeout: Option<Duration>,
+    max_idle_per_host: usize,
+}
+
+impl<T> Pool<T> {
+    pub fn new(idle_timeout: Option<Duration>, max_idle_per_host: usize) -> Self {
+        Self {
+            idle: HashMap::new(),
+            idle_timeout,
+            max_idle_per_host,
+        }
+    }
+
+    pub fn set_idle_timeout(&mut self, idle_timeout: Option<Duration>) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    pub fn set_max_idle_per_host(&mut self, max: usize) {
+        self.max_idle_per_host = max;
+        for list in self.idle.values_mut() {
+            let len = list.len();
+            if len > max {
+                list.drain(..len - max);
+            }
+        }
+        self.idle.retain(|_, list| !list.is_empty());
+    }
+
+    /// take the most recently used connection, the expired connections will be dropped
+    pub fn checkout(&mut self, key: &PoolKey) -> Option<T> {
+        let idle_timeout = self.idle_timeout;
+        let (stream, empty) = {
+            let list = self.idle.get_mut(key)?;
+            let mut stream = None;
+            while let Some(idle) = list.pop() {
+                match idle_timeout {
+                    Some(timeout) if idle.idle_at.elapsed() > timeout => {
+                        trace!("drop expired connection of {:?}", key);
+                        // the connections before this one are older, all of them are expired
+                        list.clear();
+                    }
+                    _ => {
+                        stream = Some(idle.stream);
+                        break;
+                    }
+                }
+            }
+            (stream, list.is_empty())
+        };
+        if empty {
+            self.idle.remove(key);
+        }
+        stream
+    }
+
+    /// put back the connection so it can be reused, the oldest one is dropped if the pool is full
+    pub fn checkin(&mut self, key: PoolKey, stream: T) {
+        if self.max_idle_per_host == 0 {
+            return;
+        }
+        let list = self.idle.entry(key).or_default();
+        if list.len() >= self.max_idle_per_host {
+            list.remove(0);
+        }
+        list.push(Idle {
+            stream,
+            idle_at: Instant::now(),
+        });
+    }
+
+    /// the number of idle connections for given key
+    #[cfg(test)]
+    pub fn idle_count(&self, key: &PoolKey) -> usize {
+        self.idle.get(key).map(|list| list.len()).unwrap_or(0)
+    }
+
+    /// drop all idle connections
+    pub fn clear(&mut self) {
+        self.idle.clear();
+    }
+}
+
+impl<T> fmt::Debug for Pool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let idle: HashMap<&PoolKey, usize> = self.idle.iter()
+            .map(|(key, list)| (key, list.len()))
+            .collect();
+        f.debug_struct("Pool")
+            .field("idle", &idle)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("max_idle_per_host", &self.max_idle_per_host)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use url::Url;
+
+    use super::{Pool, PoolKey};
+
+    fn key(url: &str) -> PoolKey {
+        PoolKey::from_url(&Url::parse(url).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_pool_key() {
+        assert_eq!(key("http://example.com/a"), key("http://example.com:80/b?c=d"));
+        assert_ne!(key("http://example.com/"), key("https://example.com/"));
+        assert_ne!(key("http://example.com/"), key("http://example.com:8080/"));
+        assert_ne!(key("http://example.com/"), key("http://www.example.com/"));
+        let proxy = Url::parse("http://127.0.0.1:3128").unwrap();
+        assert_ne!(key("http://example.com/"), key("http://example.com/").via(&proxy));
+    }
+
+    #[test]
+    fn test_checkout_most_recent() {
+        let mut pool = Pool::new(None, 2);
+        pool.checkin(key("http://a.com"), 1);
+        pool.checkin(key("http://a.com"), 2);
+        pool.checkin(key("http://a.com"), 3);
+        pool.checkin(key("http://b.com"), 4);
+        // the oldest one is dropped
+        assert_eq!(2, pool.idle_count(&key("http://a.com")));
+        assert_eq!(Some(3), pool.checkout(&key("http://a.com")));
+        assert_eq!(Some(2), pool.checkout(&key("http://a.com")));
+        assert_eq!(None, pool.checkout(&key("http://a.com")));
+        assert_eq!(Some(4), pool.checkout(&key("http://b.com")));
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let mut pool = Pool::new(Some(Duration::from_millis(10)), 4);
+        pool.checkin(key("http://a.com"), 1);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(None, pool.checkout(&key("http://a.com")));
+        assert_eq!(0, pool.idle_count(&key("http://a.com")));
+    }
+}
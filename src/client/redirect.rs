@@ -0,0 +1,301 @@
+use std::fmt;
+
+use url::Url;
+
+use crate::body::{Body, BodyKind};
+use crate::error::{BodyError, Error, RedirectError, Result};
+use crate::header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HeaderMap, HeaderValue, HOST, LOCATION, SET_COOKIE, TRANSFER_ENCODING};
+use crate::method::Method;
+use crate::request::Request;
+use crate::response::Response;
+use crate::status::StatusCode;
+use crate::version::Version;
+
+/// the default maximum number of redirects
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// how `HttpClient` follows the redirect responses
+pub enum RedirectPolicy {
+    /// never follow redirects, the 3xx response is returned as-is
+    None,
+    /// follow at most N redirects, an error is returned if exceeded
+    Limited(usize),
+    /// decide by the closure, the closure sees the status, the next url and visited urls
+    Custom(Box<dyn Fn(&RedirectAttempt<'_>) -> RedirectAction + Send + Sync>),
+}
+
+impl RedirectPolicy {
+    /// create a custom redirect policy
+    ///
+    /// ```
+    /// use request_rs::produce::*;
+    ///
+    /// let policy = RedirectPolicy::custom(|attempt| {
+    ///     if attempt.previous().len() > 5 {
+    ///         attempt.error("too many redirects")
+    ///     } else if attempt.url().host_str() == Some("example.com") {
+    ///         attempt.stop()
+    ///     } else {
+    ///         attempt.follow()
+    ///     }
+    /// });
+    /// let mut client = HttpClient::http();
+    /// client.set_redirect_policy(policy);
+    /// ```
+    pub fn custom<F>(policy: F) -> Self
+        where F: Fn(&RedirectAttempt<'_>) -> RedirectAction + Send + Sync + 'static {
+        RedirectPolicy::Custom(Box::new(policy))
+    }
+
+    /// check the redirect attempt
+    fn check(&self, attempt: &RedirectAttempt<'_>) -> RedirectAction {
+        match self {
+            RedirectPolicy::None => attempt.stop(),
+            RedirectPolicy::Limited(max) if attempt.previous().len() > *max => {
+                attempt.error("too many redirects")
+            }
+            RedirectPolicy::Limited(_) => attempt.follow(),
+            RedirectPolicy::Custom(policy) => policy(attempt),
+        }
+    }
+}
+
+impl Default for RedirectPolicy {
+    /// follow at most 10 redirects
+    fn default() -> Self {
+        RedirectPolicy::Limited(DEFAULT_MAX_REDIRECTS)
+    }
+}
+
+impl fmt::Debug for RedirectPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedirectPolicy::None => f.write_str("None"),
+            RedirectPolicy::Limited(max) => f.debug_tuple("Limited").field(max).finish(),
+            RedirectPolicy::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+/// a redirect the client is going to follow
+#[derive(Debug)]
+pub struct RedirectAttempt<'a> {
+    status: StatusCode,
+    next: &'a Url,
+    previous: &'a [Url],
+}
+
+impl<'a> RedirectAttempt<'a> {
+    /// the status of redirect response
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// the url going to redirect to
+    pub fn url(&self) -> &Url {
+        self.next
+    }
+
+    /// the visited urls, the first one is the url of original request
+    pub fn previous(&self) -> &[Url] {
+        self.previous
+    }
+
+    /// follow the redirect
+    pub fn follow(&self) -> RedirectAction {
+        RedirectAction::Follow
+    }
+
+    /// don't follow, return the redirect response
+    pub fn stop(&self) -> RedirectAction {
+        RedirectAction::Stop
+    }
+
+    /// don't follow, return an error
+    pub fn error(&self, msg: &str) -> RedirectAction {
+        RedirectAction::Error(msg.to_owned())
+    }
+}
+
+/// the decision of `RedirectPolicy`
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedirectAction {
+    /// follow the redirect
+    Follow,
+    /// return the redirect response
+    Stop,
+    /// return an error with the message
+    Error(String),
+}
+
+/// the urls visited for a request, stored in the `Extensions` of response.
+/// the first one is the url of original request, the last one is the url of the response
+///
+/// ```no_run
+/// use request_rs::produce::*;
+///
+/// let resp = HttpClient::get("http://www.example.com/", None, None).unwrap();
+/// let history = resp.extensions().get::<RedirectHistory>().unwrap();
+/// println!("{:?}", history.urls());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedirectHistory {
+    urls: Vec<Url>,
+}
+
+impl RedirectHistory {
+    /// all visited urls
+    pub fn urls(&self) -> &[Url] {
+        &self.urls
+    }
+
+    /// the url of the final response
+    pub fn final_url(&self) -> &Url {
+        self.urls.last().expect("history contains the request url")
+    }
+}
+
+/// the state of following redirects for a request
+pub(crate) struct Redirect {
+    method: Method,
+    version: Version,
+    headers: HeaderMap,
+    body: Body,
+    urls: Vec<Url>,
+    /// the urls and methods requested since the last `Set-Cookie`, repeating one of them is a loop
+    visited: Vec<(Url, Method)>,
+}
+
+impl Redirect {
+    /// remember the request, since the request will be consumed by sending
+    pub fn new(req: &Request<Body>, url: Url) -> Self {
+        Self {
+            method: req.method().clone(),
+            version: req.version(),
+            headers: req.headers().clone(),
+            body: req.body().clone(),
+            visited: vec![(url.clone(), req.method().clone())],
+            urls: vec![url],
+        }
+    }
+
+    /// build the next request if the response should be followed
+    pub fn next(&mut self, policy: &RedirectPolicy, resp: &Response<Body>) -> Result<Option<Request<Body>>> {
+        let status = resp.status();
+        match status {
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => {}
+            _ => return Ok(None),
+        }
+        let location = match resp.headers().get(LOCATION) {
+            Some(location) => location.to_str()?,
+            None => return Ok(None),
+        };
+        let current = self.urls.last().expect("history contains the request url").clone();
+        let next = current.join(location)?;
+
+        let attempt = RedirectAttempt {
+            status,
+            next: &next,
+            previous: &self.urls,
+        };
+        match policy.check(&attempt) {
+            RedirectAction::Follow => {}
+            RedirectAction::Stop => return Ok(None),
+            RedirectAction::Error(msg) => return Err(Error::from(RedirectError::new(&msg))),
+        }
+        debug!("redirect {} to {}", current, next);
+
+        // RFC 7231 section 6.4: user agents change POST to GET for 301 and 302,
+        // 303 always change to GET, 307 and 308 keep the method and body
+        let change_to_get = match status {
+            StatusCode::SEE_OTHER => self.method != Method::HEAD,
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => self.method == Method::POST,
+            _ => false,
+        };
+        if change_to_get {
+            self.method = Method::GET;
+            self.body = Body::empty();
+            for name in &[CONTENT_LENGTH, CONTENT_TYPE, CONTENT_ENCODING, TRANSFER_ENCODING] {
+                self.headers.remove(name);
+            }
+        }
+        // the request may be different after the cookies changed, e.g. back to the page after login
+        if resp.headers().contains_key(SET_COOKIE) {
+            self.visited.clear();
+        }
+        if self.visited.contains(&(next.clone(), self.method.clone())) {
+            return Err(Error::from(RedirectError::new(&format!("redirect loop detected at {}", next))));
+        }
+        self.visited.push((next.clone(), self.method.clone()));
+        if !resendable(&self.body) {
+            return Err(Error::from(RedirectError::new(&format!(
+                "the stream body can't be sent again to follow {} redirect to {}", status, next))));
+        }
+        // don't leak credentials to another origin
+        if current.origin() != next.origin() {
+            self.headers.remove(AUTHORIZATION);
+            self.headers.remove(COOKIE);
+        }
+        if self.headers.contains_key(HOST) {
+            self.headers.insert(HOST, host_header(&next)?);
+        }
+
+        let req = Request::builder()
+            .method(self.method.clone())
+            .version(self.version)
+            .uri(next.clone())
+            .replace_header_map(self.headers.clone())
+            .body(self.body.clone())?;
+        self.urls.push(next);
+        Ok(Some(req))
+    }
+
+    /// build the last request again with `authorization`,
+    /// the body is omitted unless `with_body`, e.g. for the first message of a handshake
+    pub fn authorize(&self, authorization: HeaderValue, with_body: bool) -> Result<Request<Body>> {
+        let mut headers = self.headers.clone();
+        headers.insert(AUTHORIZATION, authorization);
+        let body = if with_body {
+            if !resendable(&self.body) {
+                return Err(Error::from(BodyError::new("the stream body can't be sent again for authentication")));
+            }
+            self.body.clone()
+        } else {
+            for name in &[CONTENT_LENGTH, CONTENT_TYPE, CONTENT_ENCODING, TRANSFER_ENCODING] {
+                headers.remove(name);
+            }
+            Body::empty()
+        };
+        let url = self.urls.last().expect("history contains the request url").clone();
+        Request::builder()
+            .method(self.method.clone())
+            .version(self.version)
+            .uri(url)
+            .replace_header_map(headers)
+            .body(body)
+    }
+
+    /// the visited urls
+    pub fn into_history(self) -> RedirectHistory {
+        RedirectHistory { urls: self.urls }
+    }
+}
+
+/// the body can be sent again, the stream body has been consumed by the previous request unless it's read from file
+fn resendable(body: &Body) -> bool {
+    match body.kind() {
+        BodyKind::Stream(stream) => stream.is_replayable(),
+        _ => true,
+    }
+}
+
+/// the value of `Host` header for given url
+pub(crate) fn host_header(url: &Url) -> Result<HeaderValue> {
+    let host = url.host_str().ok_or(Error::from(crate::error::InvalidUrl::new("missing host")))?;
+    let value = match url.port() {
+        Some(port) => HeaderValue::from_str(&format!("{}:{}", host, port))?,
+        None => HeaderValue::from_str(host)?,
+    };
+    Ok(value)
+}
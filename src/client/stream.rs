@@ -0,0 +1,121 @@
+use std::fmt;
+use std::io::{self, ErrorKind, Read};
+use std::sync::{Mutex, Weak};
+
+use bytes::BytesMut;
+
+use crate::client::pool::{Pool, PoolKey};
+use crate::proto::Decoder;
+
+/// the size of buffer used to read from connection
+const READ_BUFFER_SIZE: usize = 8192;
+
+/// read the response body from connection lazily,
+/// the connection is put back to pool once the whole body is read,
+/// it's closed if the reader is dropped before that
+pub(crate) struct ResponseReader<S> {
+    stream: Option<S>,
+    decoder: Decoder,
+    /// the received bytes not decoded yet
+    buf: BytesMut,
+    /// the decoded body data not read yet
+    out: BytesMut,
+    done: bool,
+    keep_alive: bool,
+    key: Option<PoolKey>,
+    pool: Weak<Mutex<Pool<S>>>,
+}
+
+impl<S: Read> ResponseReader<S> {
+    /// the body is decoded by `decoder`, `buf` is the bytes received after the response head
+    pub fn new(decoder: Decoder, buf: BytesMut, keep_alive: bool) -> Self {
+        Self {
+            stream: None,
+            decoder,
+            buf,
+            out: BytesMut::new(),
+            done: false,
+            keep_alive,
+            key: None,
+            pool: Weak::new(),
+        }
+    }
+
+    /// move the connection into reader, it's put back to `pool` by `key` after the body is read
+    pub fn attach(mut self, stream: Option<S>, key: PoolKey, pool: Weak<Mutex<Pool<S>>>, close: bool) -> io::Result<Self> {
+        self.stream = stream;
+        self.key = Some(key);
+        self.pool = pool;
+        self.keep_alive = self.keep_alive && !close;
+        // the body may be empty or already received with the head
+        self.decode()?;
+        Ok(self)
+    }
+
+    /// decode the received bytes
+    fn decode(&mut self) -> io::Result<()> {
+        if self.decoder.decode(&mut self.buf, &mut self.out)? {
+            self.finish();
+        }
+        Ok(())
+    }
+
+    /// the whole body is read, reuse the connection if possible
+    fn finish(&mut self) {
+        self.done = true;
+        let stream = match self.stream.take() {
+            Some(stream) => stream,
+            None => return,
+        };
+        // the bytes after the body mean the server doesn't follow the protocol
+        if !self.keep_alive || !self.buf.is_empty() {
+            return;
+        }
+        if let (Some(pool), Some(key)) = (self.pool.upgrade(), self.key.take()) {
+            if let Ok(mut pool) = pool.lock() {
+                pool.checkin(key, stream);
+            }
+        }
+    }
+}
+
+impl<S: Read> Read for ResponseReader<S> {
+    fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if !self.out.is_empty() {
+                let n = std::cmp::min(dst.len(), self.out.len());
+                dst[..n].copy_from_slice(&self.out.split_to(n));
+                return Ok(n);
+            }
+            if self.done {
+                return Ok(0);
+            }
+            let stream = self.stream.as_mut()
+                .ok_or_else(|| io::Error::from(ErrorKind::NotConnected))?;
+            let mut chunk = [0u8; READ_BUFFER_SIZE];
+            let n = match stream.read(&mut chunk) {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if n == 0 {
+                self.decoder.decode_eof()?;
+                self.keep_alive = false;
+                self.finish();
+                continue;
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+            self.decode()?;
+        }
+    }
+}
+
+impl<S> fmt::Debug for ResponseReader<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseReader")
+            .field("decoder", &self.decoder)
+            .field("done", &self.done)
+            .field("keep_alive", &self.keep_alive)
+            .finish()
+    }
+}
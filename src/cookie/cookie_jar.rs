@@ -0,0 +1,275 @@
+use url::Url;
+
+use crate::cookie::CookieStore;
+use crate::cookie::cookies::Cookie;
+use crate::header::{HeaderMap, HeaderValue, SET_COOKIE};
+
+/// The default `CookieStore`, keep cookies in memory as RFC 6265
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use request_rs::produce::*;
+///
+/// let jar = Arc::new(Mutex::new(CookieJar::new()));
+/// let mut client = HttpClient::http();
+/// client.set_cookie_store(jar.clone());
+/// // after requests
+/// for cookie in jar.lock().unwrap().iter() {
+///     println!("{}", cookie);
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    /// in order of creation
+    cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    /// Construct an empty CookieJar
+    pub fn new() -> Self {
+        Self { cookies: Vec::new() }
+    }
+
+    /// store the cookie, the cookie with same name, domain and path is replaced,
+    /// the expired cookie removes the stored one
+    pub fn insert(&mut self, cookie: Cookie) {
+        match self.cookies.iter().position(|stored| stored.same_identity(&cookie)) {
+            Some(_) if cookie.is_expired() => {
+                self.cookies.retain(|stored| !stored.same_identity(&cookie));
+            }
+            // keep the creation order of the old cookie
+            Some(index) => self.cookies[index] = cookie,
+            None if cookie.is_expired() => {}
+            None => self.cookies.push(cookie),
+        }
+    }
+
+    /// parse the value of `Set-Cookie` header received from `url` and store it,
+    /// return false if the cookie is invalid
+    pub fn insert_raw(&mut self, set_cookie: &str, url: &Url) -> bool {
+        match Cookie::parse(set_cookie, url) {
+            Some(cookie) => {
+                self.insert(cookie);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// get the cookie by domain, path and name
+    pub fn get(&self, domain: &str, path: &str, name: &str) -> Option<&Cookie> {
+        self.cookies.iter()
+            .find(|cookie| cookie.domain == domain && cookie.path == path && cookie.name == name && !cookie.is_expired())
+    }
+
+    /// remove the cookie by domain, path and name
+    pub fn remove(&mut self, domain: &str, path: &str, name: &str) -> Option<Cookie> {
+        let index = self.cookies.iter()
+            .position(|cookie| cookie.domain == domain && cookie.path == path && cookie.name == name)?;
+        Some(self.cookies.remove(index))
+    }
+
+    /// the cookies should be sent to `url`, in order of RFC 6265 section 5.4:
+    /// longer path first, then earlier created first
+    pub fn matches(&self, url: &Url) -> Vec<&Cookie> {
+        let mut matched: Vec<&Cookie> = self.cookies.iter()
+            .filter(|cookie| !cookie.is_expired() && cookie.matches(url))
+            .collect();
+        // stable sort keeps the creation order
+        matched.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        matched
+    }
+
+    /// drop all expired cookies
+    pub fn remove_expired(&mut self) {
+        self.cookies.retain(|cookie| !cookie.is_expired());
+    }
+
+    /// drop all session cookies, as the session is end
+    pub fn clear_session_cookies(&mut self) {
+        self.cookies.retain(|cookie| !cookie.is_session());
+    }
+
+    /// drop all cookies
+    pub fn clear(&mut self) {
+        self.cookies.clear();
+    }
+
+    /// iterate over all stored cookies which are not expired
+    pub fn iter(&self) -> impl Iterator<Item=&Cookie> {
+        self.cookies.iter().filter(|cookie| !cookie.is_expired())
+    }
+
+    /// the number of cookies which are not expired
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// return true if there is no cookie
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&mut self, url: &Url, headers: &HeaderMap) {
+        for value in headers.get_all(SET_COOKIE) {
+            match value.to_str() {
+                Ok(set_cookie) => {
+                    if !self.insert_raw(set_cookie, url) {
+                        debug!("ignore invalid cookie {} from {}", set_cookie, url);
+                    }
+                }
+                Err(_) => debug!("ignore non-ascii cookie from {}", url),
+            }
+        }
+        self.remove_expired();
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let matched = self.matches(url);
+        if matched.is_empty() {
+            return None;
+        }
+        let value = matched.iter()
+            .map(|cookie| cookie.to_string())
+            .collect::<Vec<String>>()
+            .join("; ");
+        HeaderValue::from_str(&value).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use url::Url;
+
+    use crate::cookie::CookieStore;
+    use crate::cookie::cookies::{Cookie, parse_cookie_date, SameSite};
+    use crate::header::{HeaderMap, HeaderValue, SET_COOKIE};
+
+    use super::CookieJar;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    fn cookies(jar: &CookieJar, to: &str) -> Option<String> {
+        jar.cookies(&url(to)).map(|value| value.to_str().unwrap().to_owned())
+    }
+
+    #[test]
+    fn test_parse_attributes() {
+        let from = url("https://www.example.com/account/login");
+        let cookie = Cookie::parse("sid=abc; Domain=.Example.com; Path=/; Secure; HttpOnly; SameSite=Lax; Max-Age=60", &from).unwrap();
+        assert_eq!("sid", cookie.name());
+        assert_eq!("abc", cookie.value());
+        assert_eq!("example.com", cookie.domain());
+        assert_eq!("/", cookie.path());
+        assert!(!cookie.is_host_only());
+        assert!(cookie.is_secure());
+        assert!(cookie.is_http_only());
+        assert_eq!(Some(SameSite::Lax), cookie.same_site());
+        assert!(!cookie.is_session());
+        assert!(!cookie.is_expired());
+
+        // default path is the directory of request path
+        let cookie = Cookie::parse("a=b", &from).unwrap();
+        assert_eq!("/account", cookie.path());
+        assert_eq!("www.example.com", cookie.domain());
+        assert!(cookie.is_host_only());
+        assert!(cookie.is_session());
+
+        // domain attribute must domain-match the host
+        assert!(Cookie::parse("a=b; Domain=other.com", &from).is_none());
+        assert!(Cookie::parse("a=b; Domain=ample.com", &from).is_none());
+
+        // the top-level domain is rejected
+        assert!(Cookie::parse("a=b; Domain=com", &from).is_none());
+        assert!(Cookie::parse("a=b; Domain=.com", &from).is_none());
+        let cookie = Cookie::parse("a=b; Domain=localhost", &url("http://localhost/")).unwrap();
+        assert_eq!("localhost", cookie.domain());
+        assert!(cookie.is_host_only());
+
+        assert!(Cookie::parse("=b", &from).is_none());
+        assert!(Cookie::parse("no-equal-sign", &from).is_none());
+    }
+
+    #[test]
+    fn test_huge_max_age() {
+        let from = url("https://www.example.com/");
+        let cookie = Cookie::parse("a=b; Max-Age=9223372036854775807", &from).unwrap();
+        assert_eq!(Some(UNIX_EPOCH + Duration::from_secs(253_402_300_799)), cookie.expires());
+        assert!(!cookie.is_expired());
+    }
+
+    #[test]
+    fn test_parse_cookie_date() {
+        let expected = UNIX_EPOCH + Duration::from_secs(1445412480);
+        assert_eq!(Some(expected), parse_cookie_date("Wed, 21 Oct 2015 07:28:00 GMT"));
+        assert_eq!(Some(expected), parse_cookie_date("Wednesday, 21-Oct-15 07:28:00 GMT"));
+        assert_eq!(Some(expected), parse_cookie_date("Wed Oct 21 07:28:00 2015"));
+        assert_eq!(Some(UNIX_EPOCH), parse_cookie_date("Thu, 01 Jan 1970 00:00:00 GMT"));
+        assert_eq!(None, parse_cookie_date("Wed, 32 Oct 2015 07:28:00 GMT"));
+        assert_eq!(None, parse_cookie_date("not a date"));
+    }
+
+    #[test]
+    fn test_expires_and_max_age() {
+        let from = url("http://example.com/");
+        let cookie = Cookie::parse("a=b; Expires=Wed, 21 Oct 2015 07:28:00 GMT", &from).unwrap();
+        assert!(cookie.is_expired());
+        // Max-Age has precedence over Expires
+        let cookie = Cookie::parse("a=b; Max-Age=3600; Expires=Wed, 21 Oct 2015 07:28:00 GMT", &from).unwrap();
+        assert!(!cookie.is_expired());
+        let cookie = Cookie::parse("a=b; Max-Age=0", &from).unwrap();
+        assert!(cookie.is_expired());
+    }
+
+    #[test]
+    fn test_host_only_and_domain_cookie() {
+        let mut jar = CookieJar::new();
+        assert!(jar.insert_raw("host=1", &url("http://example.com/")));
+        assert!(jar.insert_raw("domain=2; Domain=example.com", &url("http://example.com/")));
+        assert_eq!(Some("host=1; domain=2".to_owned()), cookies(&jar, "http://example.com/"));
+        assert_eq!(Some("domain=2".to_owned()), cookies(&jar, "http://www.example.com/"));
+        assert_eq!(None, cookies(&jar, "http://notexample.com/"));
+        assert_eq!(None, cookies(&jar, "http://other.com/"));
+    }
+
+    #[test]
+    fn test_path_and_secure() {
+        let mut jar = CookieJar::new();
+        let from = url("https://example.com/");
+        jar.insert_raw("root=1; Path=/", &from);
+        jar.insert_raw("docs=2; Path=/docs", &from);
+        jar.insert_raw("token=3; Path=/; Secure", &from);
+        // longer path first
+        assert_eq!(Some("docs=2; root=1; token=3".to_owned()), cookies(&jar, "https://example.com/docs/a"));
+        assert_eq!(Some("docs=2; root=1".to_owned()), cookies(&jar, "http://example.com/docs"));
+        assert_eq!(Some("root=1".to_owned()), cookies(&jar, "http://example.com/docsx"));
+    }
+
+    #[test]
+    fn test_replace_and_evict() {
+        let mut jar = CookieJar::new();
+        let from = url("http://example.com/");
+        let mut headers = HeaderMap::new();
+        headers.append(SET_COOKIE, HeaderValue::from_static("a=1"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("b=2"));
+        jar.set_cookies(&from, &headers);
+        assert_eq!(2, jar.len());
+
+        let mut headers = HeaderMap::new();
+        headers.append(SET_COOKIE, HeaderValue::from_static("a=3"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("b=; Max-Age=0"));
+        jar.set_cookies(&from, &headers);
+        assert_eq!(Some("a=3".to_owned()), cookies(&jar, "http://example.com/"));
+        assert_eq!("3", jar.get("example.com", "/", "a").unwrap().value());
+        assert!(jar.get("example.com", "/", "b").is_none());
+
+        jar.clear_session_cookies();
+        assert!(jar.is_empty());
+    }
+}
@@ -1,28 +1,356 @@
-use std::process::exit;
-use std::time;
-use std::time::Duration;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-/// Cookie Version 0
-#[derive(Debug)]
+use url::Url;
+
+/// The `SameSite` attribute of cookie
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    /// `SameSite=Strict`
+    Strict,
+    /// `SameSite=Lax`
+    Lax,
+    /// `SameSite=None`
+    None,
+}
+
+impl SameSite {
+    /// convert SameSite to str
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+
+    /// parse the value of `SameSite` attribute, case insensitive
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "strict" => Some(SameSite::Strict),
+            "lax" => Some(SameSite::Lax),
+            "none" => Some(SameSite::None),
+            _ => None,
+        }
+    }
+}
+
+/// Http Cookie as [RFC 6265](https://tools.ietf.org/html/rfc6265)
+#[derive(Debug, Clone, PartialEq)]
 pub struct Cookie {
-    version: Option<usize>,
-    name: String,
-    value: String,
-    domain: Option<String>,
-    path: Option<String>,
-    secure: bool,
-    expires: Option<Duration>,
+    pub(crate) name: String,
+    pub(crate) value: String,
+    /// lowercase domain without leading dot
+    pub(crate) domain: String,
+    pub(crate) path: String,
+    pub(crate) host_only: bool,
+    pub(crate) secure: bool,
+    pub(crate) http_only: bool,
+    pub(crate) same_site: Option<SameSite>,
+    /// `None` for session cookie
+    pub(crate) expires: Option<SystemTime>,
 }
 
 impl Cookie {
+    /// create a session cookie only sent to `domain`
+    pub fn new(name: &str, value: &str, domain: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            domain: domain.trim_start_matches('.').to_ascii_lowercase(),
+            path: "/".to_owned(),
+            host_only: true,
+            secure: false,
+            http_only: false,
+            same_site: None,
+            expires: None,
+        }
+    }
+
+    /// parse the value of `Set-Cookie` header received from `url` as RFC 6265 section 5.2,
+    /// return `None` if the cookie should be ignored
+    pub fn parse(set_cookie: &str, url: &Url) -> Option<Self> {
+        let host = url.host_str()?.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase();
+        let mut parts = set_cookie.split(';');
+        let (name, value) = {
+            let pair = parts.next()?;
+            let index = pair.find('=')?;
+            (pair[..index].trim(), pair[index + 1..].trim())
+        };
+        if name.is_empty() {
+            return None;
+        }
+        let mut cookie = Cookie::new(name, value, &host);
+        cookie.path = default_path(url);
+
+        let mut max_age = None;
+        let mut expires = None;
+        for attr in parts {
+            let (key, value) = match attr.find('=') {
+                Some(index) => (attr[..index].trim(), attr[index + 1..].trim()),
+                None => (attr.trim(), ""),
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "expires" => {
+                    if let Some(time) = parse_cookie_date(value) {
+                        expires = Some(time);
+                    }
+                }
+                "max-age" => {
+                    // the first character must be digit or '-'
+                    let valid = value.chars().next().map(|c| c.is_ascii_digit() || c == '-').unwrap_or(false);
+                    if let (true, Ok(seconds)) = (valid, value.parse::<i64>()) {
+                        max_age = Some(if seconds <= 0 {
+                            UNIX_EPOCH
+                        } else {
+                            expiry_after(SystemTime::now(), seconds as u64)
+                        });
+                    }
+                }
+                "domain" => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if domain.is_empty() {
+                        continue;
+                    }
+                    // the domain without embedded dot is a top-level domain like `com`,
+                    // which is only allowed as a host-only cookie of the host itself
+                    if !domain.contains('.') {
+                        if domain == host {
+                            continue;
+                        }
+                        return None;
+                    }
+                    // the domain attribute must domain-match the request host
+                    if !domain_match(&host, &domain) {
+                        return None;
+                    }
+                    cookie.host_only = false;
+                    cookie.domain = domain;
+                }
+                // the path not starting with '/' is ignored
+                "path" if value.starts_with('/') => cookie.path = value.to_owned(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => cookie.same_site = SameSite::parse(value),
+                _ => {}
+            }
+        }
+        // Max-Age has precedence over Expires
+        cookie.expires = max_age.or(expires);
+        Some(cookie)
+    }
+
+    /// the name of cookie
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// the value of cookie
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// the domain of cookie
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// the path of cookie
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// the cookie only sent to the host set it if `Domain` attribute is absent
+    pub fn is_host_only(&self) -> bool {
+        self.host_only
+    }
+
+    /// the cookie only sent over secure connection
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// the `HttpOnly` attribute
+    pub fn is_http_only(&self) -> bool {
+        self.http_only
+    }
+
+    /// the `SameSite` attribute
+    pub fn same_site(&self) -> Option<SameSite> {
+        self.same_site
+    }
+
+    /// the expiry time, `None` for session cookie
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    /// the cookie is discarded when the session end
+    pub fn is_session(&self) -> bool {
+        self.expires.is_none()
+    }
+
+    /// the cookie is expired, session cookie never expires
     pub fn is_expired(&self) -> bool {
-        let now = time::SystemTime::now();
-        let now = now.elapsed().unwrap();
-        if let Some(expires) = self.expires {
-            expires <= now
+        match self.expires {
+            Some(expires) => expires <= SystemTime::now(),
+            None => false,
+        }
+    }
+
+    /// should the cookie be sent to `url`
+    pub fn matches(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase(),
+            None => return false,
+        };
+        let domain_matched = if self.host_only {
+            host == self.domain
         } else {
-            true
+            domain_match(&host, &self.domain)
+        };
+        domain_matched
+            && path_match(url.path(), &self.path)
+            && (!self.secure || url.scheme() == "https" || url.scheme() == "wss")
+    }
+
+    /// the cookie is the same one if name, domain and path are the same
+    pub(crate) fn same_identity(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+}
+
+impl fmt::Display for Cookie {
+    /// format as `name=value`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+/// RFC 6265 section 5.1.3
+pub(crate) fn domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    host.ends_with(domain)
+        && host[..host.len() - domain.len()].ends_with('.')
+        && host.parse::<IpAddr>().is_err()
+}
+
+/// RFC 6265 section 5.1.4
+pub(crate) fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+        && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+/// the default cookie path of `url`, RFC 6265 section 5.1.4
+fn default_path(url: &Url) -> String {
+    let path = url.path();
+    if !path.starts_with('/') {
+        return "/".to_owned();
+    }
+    match path.rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(index) => path[..index].to_owned(),
+    }
+}
+
+/// the latest expiry, 9999-12-31T23:59:59Z
+const MAX_EXPIRY_SECS: u64 = 253_402_300_799;
+
+/// the time `secs` seconds after `base`, clamped to the latest expiry
+pub(crate) fn expiry_after(base: SystemTime, secs: u64) -> SystemTime {
+    let max = UNIX_EPOCH + Duration::from_secs(MAX_EXPIRY_SECS);
+    match base.checked_add(Duration::from_secs(secs)) {
+        Some(time) if time < max => time,
+        _ => max,
+    }
+}
+
+/// parse date as the cookie-date algorithm of RFC 6265 section 5.1.1
+pub(crate) fn parse_cookie_date(date: &str) -> Option<SystemTime> {
+    let is_delimiter = |c: char| {
+        c == '\t' || (' '..='/').contains(&c) || (';'..='@').contains(&c)
+            || ('['..='`').contains(&c) || ('{'..='~').contains(&c)
+    };
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+    for token in date.split(is_delimiter).filter(|token| !token.is_empty()) {
+        if time.is_none() {
+            if let Some(parsed) = parse_time(token) {
+                time = Some(parsed);
+                continue;
+            }
+        }
+        if day.is_none() {
+            if let Some(parsed) = leading_digits(token, 1, 2) {
+                day = Some(parsed);
+                continue;
+            }
+        }
+        if month.is_none() && token.len() >= 3 {
+            let prefix = token[..3].to_ascii_lowercase();
+            const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+            if let Some(index) = MONTHS.iter().position(|m| *m == prefix) {
+                month = Some(index as u32 + 1);
+                continue;
+            }
+        }
+        if year.is_none() {
+            if let Some(parsed) = leading_digits(token, 2, 4) {
+                year = Some(parsed);
+                continue;
+            }
         }
     }
+    let (hour, minute, second) = time?;
+    let (day, month, mut year) = (day?, month?, year?);
+    if (70..=99).contains(&year) {
+        year += 1900;
+    } else if year <= 69 {
+        year += 2000;
+    }
+    if !(1..=31).contains(&day) || year < 1601 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let days = days_from_civil(year as i64, month, day);
+    let seconds = days * 86400 + (hour * 3600 + minute * 60 + second) as i64;
+    if seconds >= 0 {
+        Some(UNIX_EPOCH + Duration::from_secs(seconds as u64))
+    } else {
+        Some(UNIX_EPOCH - Duration::from_secs((-seconds) as u64))
+    }
+}
+
+/// parse `hh:mm:ss`, each field has 1 or 2 digits
+fn parse_time(token: &str) -> Option<(u32, u32, u32)> {
+    let mut fields = token.splitn(3, ':');
+    let hour = leading_digits(fields.next()?, 1, 2)?;
+    let minute = leading_digits(fields.next()?, 1, 2)?;
+    let second = leading_digits(fields.next()?, 1, 2)?;
+    Some((hour, minute, second))
 }
 
+/// parse the leading `min..=max` digits, the remain characters must not be digit
+fn leading_digits(token: &str, min: usize, max: usize) -> Option<u32> {
+    let len = token.chars().take_while(|c| c.is_ascii_digit()).count();
+    if len < min || len > max {
+        return None;
+    }
+    token[..len].parse().ok()
+}
+
+/// days since 1970-01-01 of the given date
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
@@ -1,8 +1,36 @@
-use crate::cookie::cookies::Cookie;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use url::Url;
+
+use crate::header::{HeaderMap, HeaderValue};
+
+pub use self::cookie_jar::CookieJar;
+pub use self::cookies::{Cookie, SameSite};
 
 mod cookies;
 mod cookie_jar;
+mod persist;
+
+/// the storage of cookies used by `HttpClient`,
+/// the client saves cookies from each response and asks the cookies for each request
+pub trait CookieStore: fmt::Debug {
+    /// store the cookies in `Set-Cookie` headers of the response received from `url`
+    fn set_cookies(&mut self, url: &Url, headers: &HeaderMap);
+
+    /// the value of `Cookie` header should be sent to `url`, `None` if no cookie matched
+    fn cookies(&self, url: &Url) -> Option<HeaderValue>;
+}
+
+/// share the store between client and caller, so the caller can inspect cookies after requests
+impl<S: CookieStore> CookieStore for Arc<Mutex<S>> {
+    fn set_cookies(&mut self, url: &Url, headers: &HeaderMap) {
+        if let Ok(mut store) = self.lock() {
+            store.set_cookies(url, headers);
+        }
+    }
 
-pub trait CookieJar {
-    fn cookie(&self, name: &str) -> Cookie;
-}
\ No newline at end of file
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        self.lock().ok().and_then(|store| store.cookies(url))
+    }
+}
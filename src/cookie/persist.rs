@@ -0,0 +1,280 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cookie::cookie_jar::CookieJar;
+use crate::cookie::cookies::{Cookie, expiry_after, SameSite};
+use crate::error::{Error, InvalidCookieFile, Result};
+
+/// the prefix of `HttpOnly` cookies used by curl
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+/// the record of cookie in json format,
+/// `expires` is the unix timestamp in seconds, absent for session cookie
+#[derive(Debug, Serialize, Deserialize)]
+struct CookieRecord {
+    name: String,
+    value: String,
+    domain: String,
+    path: String,
+    #[serde(default)]
+    host_only: bool,
+    #[serde(default)]
+    secure: bool,
+    #[serde(default)]
+    http_only: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    same_site: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires: Option<u64>,
+}
+
+impl From<&Cookie> for CookieRecord {
+    fn from(cookie: &Cookie) -> Self {
+        Self {
+            name: cookie.name.clone(),
+            value: cookie.value.clone(),
+            domain: cookie.domain.clone(),
+            path: cookie.path.clone(),
+            host_only: cookie.host_only,
+            secure: cookie.secure,
+            http_only: cookie.http_only,
+            same_site: cookie.same_site.map(|same_site| same_site.as_str().to_owned()),
+            expires: cookie.expires.map(to_timestamp),
+        }
+    }
+}
+
+impl From<CookieRecord> for Cookie {
+    fn from(record: CookieRecord) -> Self {
+        let mut cookie = Cookie::new(&record.name, &record.value, &record.domain);
+        cookie.path = record.path;
+        cookie.host_only = record.host_only;
+        cookie.secure = record.secure;
+        cookie.http_only = record.http_only;
+        cookie.same_site = record.same_site.as_ref().and_then(|value| SameSite::parse(value));
+        cookie.expires = record.expires.map(from_timestamp);
+        cookie
+    }
+}
+
+fn to_timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|dur| dur.as_secs()).unwrap_or(0)
+}
+
+/// the oversized expiry is clamped to the latest expiry
+fn from_timestamp(secs: u64) -> SystemTime {
+    expiry_after(UNIX_EPOCH, secs)
+}
+
+fn bool_flag(value: bool) -> &'static str {
+    if value { "TRUE" } else { "FALSE" }
+}
+
+impl CookieJar {
+    /// load cookies from Netscape `cookies.txt` format, as used by curl and wget,
+    /// return the number of loaded cookies. the expired cookies are ignored,
+    /// the cookies with expiry `0` are loaded as session cookies
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use request_rs::produce::*;
+    ///
+    /// let mut jar = CookieJar::new();
+    /// jar.load_netscape(File::open("cookies.txt").unwrap()).unwrap();
+    /// ```
+    pub fn load_netscape<R: Read>(&mut self, reader: R) -> Result<usize> {
+        let mut loaded = 0;
+        for (index, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let line = line.trim_end_matches(['\r', '\n']);
+            let (line, http_only) = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || Error::from(InvalidCookieFile::new(&format!("invalid cookie at line {}", index + 1)));
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 6 && fields.len() != 7 {
+                return Err(invalid());
+            }
+            let include_subdomains = match fields[1] {
+                "TRUE" => true,
+                "FALSE" => false,
+                _ => return Err(invalid()),
+            };
+            let secure = match fields[3] {
+                "TRUE" => true,
+                "FALSE" => false,
+                _ => return Err(invalid()),
+            };
+            let expires = fields[4].parse::<u64>().map_err(|_| invalid())?;
+
+            let mut cookie = Cookie::new(fields[5], fields.get(6).cloned().unwrap_or(""), fields[0]);
+            cookie.host_only = !include_subdomains && !fields[0].starts_with('.');
+            cookie.path = fields[2].to_owned();
+            cookie.secure = secure;
+            cookie.http_only = http_only;
+            cookie.expires = if expires == 0 { None } else { Some(from_timestamp(expires)) };
+            if !cookie.is_expired() {
+                self.insert(cookie);
+                loaded += 1;
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// save cookies in Netscape `cookies.txt` format, session cookies are written with expiry `0`
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use request_rs::produce::*;
+    ///
+    /// let jar = CookieJar::new();
+    /// jar.save_netscape(File::create("cookies.txt").unwrap()).unwrap();
+    /// ```
+    pub fn save_netscape<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(b"# Netscape HTTP Cookie File\n# This file was generated by request-rs! Edit at your own risk.\n\n")?;
+        for cookie in self.iter() {
+            let domain = if cookie.host_only {
+                cookie.domain.clone()
+            } else {
+                format!(".{}", cookie.domain)
+            };
+            writeln!(writer, "{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                     if cookie.http_only { HTTP_ONLY_PREFIX } else { "" },
+                     domain,
+                     bool_flag(!cookie.host_only),
+                     cookie.path,
+                     bool_flag(cookie.secure),
+                     cookie.expires.map(to_timestamp).unwrap_or(0),
+                     cookie.name,
+                     cookie.value)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// load cookies from json array, return the number of loaded cookies,
+    /// the expired cookies are ignored
+    pub fn load_json<R: Read>(&mut self, reader: R) -> Result<usize> {
+        let records: Vec<CookieRecord> = serde_json::from_reader(reader)
+            .map_err(|e| Error::from(InvalidCookieFile::new(&e.to_string())))?;
+        let mut loaded = 0;
+        for record in records {
+            let cookie = Cookie::from(record);
+            if !cookie.is_expired() {
+                self.insert(cookie);
+                loaded += 1;
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// save cookies as json array, the `expires` of session cookie is absent
+    pub fn save_json<W: Write>(&self, writer: W) -> Result<()> {
+        let records: Vec<CookieRecord> = self.iter().map(CookieRecord::from).collect();
+        serde_json::to_writer_pretty(writer, &records)
+            .map_err(|e| Error::from(InvalidCookieFile::new(&e.to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use url::Url;
+
+    use crate::cookie::cookie_jar::CookieJar;
+    use crate::cookie::cookies::SameSite;
+    use crate::error::InvalidCookieFile;
+
+    const CURL_COOKIES: &str = "# Netscape HTTP Cookie File\n\
+        # https://curl.se/docs/http-cookies.html\n\
+        \n\
+        .example.com\tTRUE\t/\tFALSE\t0\tsession\tabc\n\
+        #HttpOnly_www.example.com\tFALSE\t/account\tTRUE\t4102444800\ttoken\txyz\n\
+        example.com\tFALSE\t/\tFALSE\t946684800\texpired\t1\n\
+        example.com\tFALSE\t/\tFALSE\t0\tempty\t\n";
+
+    #[test]
+    fn test_load_netscape() {
+        let mut jar = CookieJar::new();
+        assert_eq!(3, jar.load_netscape(CURL_COOKIES.as_bytes()).unwrap());
+
+        let session = jar.get("example.com", "/", "session").unwrap();
+        assert!(!session.is_host_only());
+        assert!(session.is_session());
+
+        let token = jar.get("www.example.com", "/account", "token").unwrap();
+        assert!(token.is_host_only());
+        assert!(token.is_secure());
+        assert!(token.is_http_only());
+        assert_eq!(Some(UNIX_EPOCH + Duration::from_secs(4102444800)), token.expires());
+
+        assert_eq!("", jar.get("example.com", "/", "empty").unwrap().value());
+        assert!(jar.get("example.com", "/", "expired").is_none());
+    }
+
+    #[test]
+    fn test_netscape_round_trip() {
+        let mut jar = CookieJar::new();
+        jar.load_netscape(CURL_COOKIES.as_bytes()).unwrap();
+        let mut saved = Vec::new();
+        jar.save_netscape(&mut saved).unwrap();
+        let saved = String::from_utf8(saved).unwrap();
+        assert!(saved.starts_with("# Netscape HTTP Cookie File\n"));
+        assert!(saved.contains(".example.com\tTRUE\t/\tFALSE\t0\tsession\tabc\n"));
+        assert!(saved.contains("#HttpOnly_www.example.com\tFALSE\t/account\tTRUE\t4102444800\ttoken\txyz\n"));
+
+        let mut loaded = CookieJar::new();
+        assert_eq!(3, loaded.load_netscape(saved.as_bytes()).unwrap());
+        assert_eq!(jar.iter().collect::<Vec<_>>(), loaded.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_load_huge_expiry() {
+        let mut jar = CookieJar::new();
+        jar.load_netscape("example.com\tFALSE\t/\tFALSE\t18446744073709551615\ta\tb\n".as_bytes()).unwrap();
+        assert_eq!(Some(UNIX_EPOCH + Duration::from_secs(253_402_300_799)), jar.get("example.com", "/", "a").unwrap().expires());
+
+        let json = r#"[{"name":"a","value":"b","domain":"example.com","path":"/","host_only":true,"secure":false,"http_only":false,"expires":18446744073709551615}]"#;
+        let mut jar = CookieJar::new();
+        jar.load_json(json.as_bytes()).unwrap();
+        assert!(!jar.get("example.com", "/", "a").unwrap().is_expired());
+    }
+
+    #[test]
+    fn test_load_invalid_netscape() {
+        let mut jar = CookieJar::new();
+        let err = jar.load_netscape("example.com\tMAYBE\t/\tFALSE\t0\ta\tb\n".as_bytes()).unwrap_err();
+        assert!(err.is::<InvalidCookieFile>());
+        assert_eq!("invalid cookie at line 1", err.to_string());
+        assert!(jar.load_netscape("example.com\tFALSE\t/\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let url = Url::parse("https://www.example.com/").unwrap();
+        let mut jar = CookieJar::new();
+        jar.insert_raw("sid=abc; Domain=example.com; Secure; HttpOnly; SameSite=Strict; Max-Age=3600", &url);
+        jar.insert_raw("lang=en", &url);
+        let mut saved = Vec::new();
+        jar.save_json(&mut saved).unwrap();
+
+        let mut loaded = CookieJar::new();
+        assert_eq!(2, loaded.load_json(saved.as_slice()).unwrap());
+        let sid = loaded.get("example.com", "/", "sid").unwrap();
+        assert!(!sid.is_host_only() && sid.is_secure() && sid.is_http_only());
+        assert_eq!(Some(SameSite::Strict), sid.same_site());
+        assert!(sid.expires().unwrap() > SystemTime::now());
+        let lang = loaded.get("www.example.com", "/", "lang").unwrap();
+        assert!(lang.is_host_only());
+        assert!(lang.is_session());
+
+        assert!(loaded.load_json("{}".as_bytes()).unwrap_err().is::<InvalidCookieFile>());
+    }
+}
@@ -4,6 +4,7 @@ use std::fmt;
 use std::net::AddrParseError;
 use std::result;
 use std::string::FromUtf8Error;
+use std::sync::Arc;
 
 use crate::header;
 use crate::header::ToStrError;
@@ -148,6 +149,292 @@ impl fmt::Display for InvalidHttpHeader {
     }
 }
 
+/// Error type for tls connection
+#[derive(Debug, Clone)]
+pub struct TlsError {
+    msg: String,
+}
+
+impl TlsError {
+    /// create an error for tls connection
+    pub fn new(msg: &str) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.msg.as_str())
+    }
+}
+
+/// Error type for following redirects
+#[derive(Debug, Clone)]
+pub struct RedirectError {
+    msg: String,
+}
+
+impl RedirectError {
+    /// create an error for redirect
+    pub fn new(msg: &str) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for RedirectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.msg.as_str())
+    }
+}
+
+/// An error occurred when loading or saving cookies
+#[derive(Debug, Clone)]
+pub struct InvalidCookieFile {
+    msg: String,
+}
+
+impl InvalidCookieFile {
+    /// Construct a new InvalidCookieFile with error message
+    pub fn new(msg: &str) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for InvalidCookieFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.msg.as_str())
+    }
+}
+
+/// An error occurred when connecting through proxy
+#[derive(Debug, Clone)]
+pub struct ProxyError {
+    msg: String,
+}
+
+impl ProxyError {
+    /// Construct a new ProxyError with error message
+    pub fn new(msg: &str) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.msg.as_str())
+    }
+}
+
+/// An error occurred when reading or writing body
+#[derive(Debug, Clone)]
+pub struct BodyError {
+    msg: String,
+}
+
+impl BodyError {
+    /// Construct a new BodyError with error message
+    pub fn new(msg: &str) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.msg.as_str())
+    }
+}
+
+/// An error occurred when downloading file
+#[derive(Debug, Clone)]
+pub struct DownloadError {
+    msg: String,
+}
+
+impl DownloadError {
+    /// Construct a new DownloadError with error message
+    pub fn new(msg: &str) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.msg.as_str())
+    }
+}
+
+/// the max length of body shown in `JsonError`
+const JSON_SNIPPET_LEN: usize = 128;
+
+/// An error occurred when serializing or deserializing json
+#[derive(Debug, Clone)]
+pub struct JsonError {
+    inner: Arc<serde_json::Error>,
+    snippet: Option<String>,
+}
+
+impl JsonError {
+    /// Construct a new JsonError, `body` is the data failed to deserialize,
+    /// the beginning of it is included in the error message
+    pub fn new(err: serde_json::Error, body: Option<&[u8]>) -> Self {
+        let snippet = body.map(|body| {
+            let text = String::from_utf8_lossy(body);
+            match text.char_indices().nth(JSON_SNIPPET_LEN) {
+                Some((end, _)) => format!("{}...", &text[..end]),
+                None => text.into_owned(),
+            }
+        });
+        Self {
+            inner: Arc::new(err),
+            snippet,
+        }
+    }
+
+    /// the line where the error occurred, starting at 1, 0 if the error is not caused by input data
+    pub fn line(&self) -> usize {
+        self.inner.line()
+    }
+
+    /// the column where the error occurred, starting at 1, 0 if the error is not caused by input data
+    pub fn column(&self) -> usize {
+        self.inner.column()
+    }
+
+    /// the beginning of the body failed to deserialize
+    pub fn snippet(&self) -> Option<&str> {
+        self.snippet.as_deref()
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.snippet {
+            Some(ref snippet) => write!(f, "{}, body: {}", self.inner, snippet),
+            None => fmt::Display::fmt(&self.inner, f),
+        }
+    }
+}
+
+impl error::Error for JsonError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&*self.inner)
+    }
+}
+
+/// An error occurred when encoding or decoding urlencoded form
+#[derive(Debug, Clone)]
+pub struct FormError {
+    msg: String,
+}
+
+impl FormError {
+    /// Construct a new FormError with error message
+    pub fn new(msg: &str) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.msg.as_str())
+    }
+}
+
+/// An error occurred when parsing multipart body
+#[derive(Debug, Clone)]
+pub struct MultipartError {
+    msg: String,
+}
+
+impl MultipartError {
+    /// Construct a new MultipartError with error message
+    pub fn new(msg: &str) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.msg.as_str())
+    }
+}
+
+/// An error occurred in HTTP/2 connection or stream
+#[derive(Debug, Clone)]
+pub struct Http2Error {
+    msg: String,
+}
+
+impl Http2Error {
+    /// Construct a new Http2Error with error message
+    pub fn new(msg: &str) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for Http2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.msg.as_str())
+    }
+}
+
+/// The coalesced request failed to deliver the response to waiting requests
+#[derive(Debug, Clone)]
+pub struct CoalesceError {
+    msg: String,
+}
+
+impl CoalesceError {
+    /// Construct a new CoalesceError with error message
+    pub fn new(msg: &str) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for CoalesceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.msg.as_str())
+    }
+}
+
+/// An error occurred in NTLM authentication
+#[derive(Debug, Clone)]
+pub struct NtlmError {
+    msg: String,
+}
+
+impl NtlmError {
+    /// Construct a new NtlmError with error message
+    pub fn new(msg: &str) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for NtlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.msg.as_str())
+    }
+}
+
+/// An error occurred in WebSocket handshake or connection
+#[derive(Debug, Clone)]
+pub struct WebSocketError {
+    msg: String,
+}
+
+impl WebSocketError {
+    /// Construct a new WebSocketError with error message
+    pub fn new(msg: &str) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for WebSocketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.msg.as_str())
+    }
+}
+
 
 #[derive(Clone)]
 enum ErrorKind {
@@ -164,6 +451,19 @@ enum ErrorKind {
     FromUtf8Error(FromUtf8Error),
     InvalidHttpHeader(InvalidHttpHeader),
     ParseError(httparse::Error),
+    TlsError(TlsError),
+    RedirectError(RedirectError),
+    InvalidCookieFile(InvalidCookieFile),
+    ProxyError(ProxyError),
+    BodyError(BodyError),
+    DownloadError(DownloadError),
+    JsonError(JsonError),
+    FormError(FormError),
+    MultipartError(MultipartError),
+    Http2Error(Http2Error),
+    CoalesceError(CoalesceError),
+    NtlmError(NtlmError),
+    WebSocketError(WebSocketError),
 }
 
 impl fmt::Debug for Error {
@@ -204,6 +504,19 @@ impl Error {
             InvalidHttpVersion(ref e) => e,
             InvalidHttpHeader(ref e) => e,
             ParseError(ref e) => e,
+            TlsError(ref e) => e,
+            RedirectError(ref e) => e,
+            InvalidCookieFile(ref e) => e,
+            ProxyError(ref e) => e,
+            BodyError(ref e) => e,
+            DownloadError(ref e) => e,
+            JsonError(ref e) => e,
+            FormError(ref e) => e,
+            MultipartError(ref e) => e,
+            Http2Error(ref e) => e,
+            CoalesceError(ref e) => e,
+            NtlmError(ref e) => e,
+            WebSocketError(ref e) => e,
         }
     }
 }
@@ -224,10 +537,32 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        let kind = match err.inner {
+            ErrorKind::IoError(ref e) => e.repr,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, err.to_string())
+    }
+}
+
 impl_error!(InvalidHttpVersion);
 impl_error!(InvalidUrl);
 impl_error!(IoError);
 impl_error!(InvalidHttpHeader);
+impl_error!(TlsError);
+impl_error!(RedirectError);
+impl_error!(InvalidCookieFile);
+impl_error!(ProxyError);
+impl_error!(BodyError);
+impl_error!(DownloadError);
+impl_error!(FormError);
+impl_error!(MultipartError);
+impl_error!(Http2Error);
+impl_error!(CoalesceError);
+impl_error!(NtlmError);
+impl_error!(WebSocketError);
 
 from_error!(httparse::Error,ErrorKind::ParseError);
 from_error!(InvalidHttpHeader,ErrorKind::InvalidHttpHeader);
@@ -242,6 +577,32 @@ from_error!(header::InvalidHeaderName,ErrorKind::HeaderName);
 from_error!(header::InvalidHeaderValue,ErrorKind::HeaderValue);
 from_error!(AddrParseError,ErrorKind::SocketParseError);
 from_error!(FromUtf8Error,ErrorKind::FromUtf8Error);
+from_error!(TlsError,ErrorKind::TlsError);
+from_error!(RedirectError,ErrorKind::RedirectError);
+from_error!(InvalidCookieFile,ErrorKind::InvalidCookieFile);
+from_error!(ProxyError,ErrorKind::ProxyError);
+from_error!(BodyError,ErrorKind::BodyError);
+from_error!(DownloadError,ErrorKind::DownloadError);
+from_error!(JsonError,ErrorKind::JsonError);
+from_error!(FormError,ErrorKind::FormError);
+from_error!(MultipartError,ErrorKind::MultipartError);
+from_error!(Http2Error,ErrorKind::Http2Error);
+from_error!(CoalesceError,ErrorKind::CoalesceError);
+from_error!(NtlmError,ErrorKind::NtlmError);
+from_error!(WebSocketError,ErrorKind::WebSocketError);
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::from(JsonError::new(err, None))
+    }
+}
+
+#[cfg(feature = "tls")]
+impl From<rustls::Error> for Error {
+    fn from(err: rustls::Error) -> Self {
+        Error::from(TlsError::new(err.to_string().as_str()))
+    }
+}
 
 impl From<std::convert::Infallible> for Error {
     fn from(err: std::convert::Infallible) -> Error {
@@ -269,4 +630,16 @@ mod tests {
             panic!("Bad status allowed!");
         }
     }
+
+    #[test]
+    fn json_error_has_body_snippet() {
+        let body = format!("[{}", "1,".repeat(100));
+        let err = serde_json::from_str::<Vec<u8>>(&body).unwrap_err();
+        let err = Error::from(JsonError::new(err, Some(body.as_bytes())));
+        assert!(err.is::<JsonError>());
+        assert!(error::Error::source(&err).unwrap().is::<serde_json::Error>());
+        let json = err.get_ref().downcast_ref::<JsonError>().unwrap();
+        assert_eq!(1, json.line());
+        assert_eq!(format!("{}...", &body[..JSON_SNIPPET_LEN]), json.snippet().unwrap());
+    }
 }
@@ -0,0 +1,472 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::marker::PhantomData;
+
+use bytes::{Buf, BytesMut};
+use serde::de::{DeserializeOwned, Error as _};
+
+use crate::body::Body;
+use crate::error::{Error, JsonError, Result};
+use crate::header::CONTENT_TYPE;
+use crate::response::Response;
+
+const READ_BUFFER_SIZE: usize = 8192;
+/// the default max size of an item
+const DEFAULT_MAX_ITEM_SIZE: usize = 16 << 20;
+/// the record separator of JSON text sequences
+const RS: u8 = 0x1E;
+
+/// How the JSON items are framed in the body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// newline-delimited JSON (NDJSON, JSON Lines), the blank lines are skipped
+    Lines,
+    /// JSON text sequences, each item is preceded by RS (0x1E), RFC 7464
+    Sequence,
+    /// the elements of a top-level JSON array
+    Array,
+}
+
+impl JsonFormat {
+    /// the format of media type, `application/x-ndjson`, `application/jsonl` and `application/json-seq` are known,
+    /// `None` for the other media types
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let media_type = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        match media_type.as_str() {
+            "application/x-ndjson" | "application/ndjson" | "application/jsonl" | "application/x-jsonlines"
+            | "application/jsonlines" | "application/stream+json" => Some(JsonFormat::Lines),
+            "application/json-seq" => Some(JsonFormat::Sequence),
+            "application/json" => Some(JsonFormat::Array),
+            _ => None,
+        }
+    }
+}
+
+/// The state of top-level array
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArrayState {
+    /// expect `[`
+    Start,
+    /// expect the first element or `]`
+    First,
+    /// expect an element
+    Element,
+    /// expect `,` or `]`
+    Separator,
+    /// only whitespace is allowed after `]`
+    End,
+}
+
+/// The scanner finding the end of an array element
+#[derive(Debug, Default)]
+struct Scanner {
+    /// the bytes of buffer scanned
+    pos: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+/// An iterator deserializing the JSON items one by one while reading,
+/// only the item being deserialized is buffered.
+///
+/// The malformed item of `Lines` and `Sequence` is yielded as error and skipped,
+/// the iteration ends after an error of `Array` or reader.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use request_rs::produce::*;
+///
+/// let body = "{\"level\":\"info\"}\n\n{\"level\":\"warn\"}\n".as_bytes();
+/// let events: Vec<HashMap<String, String>> = JsonStream::new(body, JsonFormat::Lines)
+///     .collect::<Result<_>>().unwrap();
+/// assert_eq!(2, events.len());
+/// assert_eq!("warn", events[1]["level"]);
+/// ```
+pub struct JsonStream<R, T> {
+    reader: R,
+    format: JsonFormat,
+    buf: BytesMut,
+    eof: bool,
+    done: bool,
+    max_item_size: usize,
+    array: ArrayState,
+    scanner: Scanner,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<R: Read, T: DeserializeOwned> JsonStream<R, T> {
+    /// Create a stream reading the items of `format` from `reader`
+    pub fn new(reader: R, format: JsonFormat) -> Self {
+        Self {
+            reader,
+            format,
+            buf: BytesMut::with_capacity(READ_BUFFER_SIZE),
+            eof: false,
+            done: false,
+            max_item_size: DEFAULT_MAX_ITEM_SIZE,
+            array: ArrayState::Start,
+            scanner: Scanner::default(),
+            _item: PhantomData,
+        }
+    }
+
+    /// Set the max size of an item, the iteration fails if it's exceeded.
+    ///
+    /// Default is 16 MiB.
+    pub fn max_item_size(mut self, size: usize) -> Self {
+        self.max_item_size = size;
+        self
+    }
+
+    /// the format of items
+    pub fn format(&self) -> JsonFormat {
+        self.format
+    }
+
+    /// the underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// read more data to buffer, return false at the end of reader
+    fn fill(&mut self) -> Result<bool> {
+        if self.eof {
+            return Ok(false);
+        }
+        if self.buf.len() > self.max_item_size {
+            return Err(framing_error(&format!("the item exceeds the limit {} bytes", self.max_item_size)));
+        }
+        let mut chunk = [0u8; READ_BUFFER_SIZE];
+        let n = loop {
+            match self.reader.read(&mut chunk) {
+                Ok(n) => break n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::from(e)),
+            }
+        };
+        if n == 0 {
+            self.eof = true;
+            return Ok(false);
+        }
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(true)
+    }
+
+    /// the next line which is not blank
+    fn next_line(&mut self) -> Result<Option<Result<T>>> {
+        loop {
+            let line = match self.buf.iter().position(|&b| b == b'\n') {
+                Some(end) => self.buf.split_to(end + 1),
+                None if self.fill()? => continue,
+                None if self.buf.is_empty() => return Ok(None),
+                None => self.buf.split(),
+            };
+            let line = trim(&line);
+            if !line.is_empty() {
+                return Ok(Some(deserialize(line)));
+            }
+        }
+    }
+
+    /// the next record between RS, the empty records are skipped
+    fn next_record(&mut self) -> Result<Option<Result<T>>> {
+        loop {
+            if self.buf.first().map(|&b| b != RS).unwrap_or(false) {
+                // the data before RS is not a record
+                let (start, eof) = match self.buf.iter().position(|&b| b == RS) {
+                    Some(start) => (start, false),
+                    None if self.fill()? => continue,
+                    None => (self.buf.len(), true),
+                };
+                let garbage = !trim(&self.buf[..start]).is_empty();
+                self.buf.advance(start);
+                if garbage {
+                    return Ok(Some(Err(framing_error("the JSON text is not preceded by RS"))));
+                }
+                if eof {
+                    return Ok(None);
+                }
+            }
+            let record = match self.buf.iter().skip(1).position(|&b| b == RS) {
+                Some(end) => self.buf.split_to(end + 1),
+                None if self.fill()? => continue,
+                None if self.buf.is_empty() => return Ok(None),
+                None => self.buf.split(),
+            };
+            let record = trim(&record[1..]);
+            if !record.is_empty() {
+                return Ok(Some(deserialize(record)));
+            }
+        }
+    }
+
+    /// the next element of array
+    fn next_element(&mut self) -> Result<Option<T>> {
+        loop {
+            self.skip_whitespace();
+            if self.buf.is_empty() {
+                if self.fill()? {
+                    continue;
+                }
+                return match self.array {
+                    ArrayState::End => Ok(None),
+                    ArrayState::Start => Err(framing_error("the body is empty, expected a JSON array")),
+                    _ => Err(framing_error("the JSON array is truncated")),
+                };
+            }
+            let byte = self.buf[0];
+            match (self.array, byte) {
+                (ArrayState::Start, b'[') => {
+                    self.buf.advance(1);
+                    self.array = ArrayState::First;
+                }
+                (ArrayState::First, b']') | (ArrayState::Separator, b']') => {
+                    self.buf.advance(1);
+                    self.array = ArrayState::End;
+                }
+                (ArrayState::Separator, b',') => {
+                    self.buf.advance(1);
+                    self.array = ArrayState::Element;
+                }
+                (ArrayState::First, _) | (ArrayState::Element, _) => {
+                    let end = match self.scan() {
+                        Some(end) => end,
+                        None if self.fill()? => continue,
+                        None => return Err(framing_error("the JSON array is truncated")),
+                    };
+                    let element = self.buf.split_to(end);
+                    self.scanner = Scanner::default();
+                    self.array = ArrayState::Separator;
+                    return deserialize(&element).map(Some);
+                }
+                (ArrayState::Start, _) => return Err(framing_error("expected a JSON array")),
+                (ArrayState::Separator, _) => return Err(framing_error("expected `,` or `]` after array element")),
+                (ArrayState::End, _) => return Err(framing_error("trailing data after the JSON array")),
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let n = self.buf.iter().take_while(|b| b.is_ascii_whitespace()).count();
+        self.buf.advance(n);
+    }
+
+    /// scan the buffer for the end of element starting at the beginning,
+    /// the state is kept between calls, `None` if more data is needed
+    fn scan(&mut self) -> Option<usize> {
+        let scanner = &mut self.scanner;
+        while scanner.pos < self.buf.len() {
+            let byte = self.buf[scanner.pos];
+            if scanner.in_string {
+                if scanner.escaped {
+                    scanner.escaped = false;
+                } else if byte == b'\\' {
+                    scanner.escaped = true;
+                } else if byte == b'"' {
+                    scanner.in_string = false;
+                }
+            } else {
+                match byte {
+                    b'"' => scanner.in_string = true,
+                    b'{' | b'[' => scanner.depth += 1,
+                    b'}' | b']' if scanner.depth > 0 => scanner.depth -= 1,
+                    b',' | b']' if scanner.depth == 0 => return Some(scanner.pos),
+                    _ => {}
+                }
+            }
+            scanner.pos += 1;
+        }
+        None
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for JsonStream<R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = match self.format {
+            JsonFormat::Lines => self.next_line(),
+            JsonFormat::Sequence => self.next_record(),
+            JsonFormat::Array => self.next_element().map(|element| element.map(Ok)),
+        };
+        match next {
+            Ok(Some(item)) => Some(item),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<R, T> fmt::Debug for JsonStream<R, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonStream")
+            .field("format", &self.format)
+            .field("buffered", &self.buf.len())
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+impl Response<Body> {
+    /// deserialize the JSON items of body one by one, the format is chosen by `Content-Type`,
+    /// the body is read as a JSON array if the media type is unknown.
+    ///
+    /// The items are read from connection lazily if the response is returned by `HttpClient::send_streaming`.
+    ///
+    /// ```no_run
+    /// use std::collections::HashMap;
+    /// use request_rs::produce::*;
+    ///
+    /// let mut client = HttpClient::http();
+    /// let req = Request::builder()
+    ///     .uri(Url::parse("http://www.example.com/logs/export").unwrap())
+    ///     .header("Accept", "application/x-ndjson")
+    ///     .body(Body::empty()).unwrap();
+    /// let mut resp = client.send_streaming(req).unwrap();
+    /// for event in resp.json_stream::<HashMap<String, String>>() {
+    ///     println!("{:?}", event.unwrap());
+    /// }
+    /// ```
+    pub fn json_stream<T: DeserializeOwned>(&mut self) -> JsonStream<&mut Body, T> {
+        let format = self.headers().get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(JsonFormat::from_content_type)
+            .unwrap_or(JsonFormat::Array);
+        self.json_stream_as(format)
+    }
+
+    /// deserialize the JSON items of body in `format` one by one regardless of `Content-Type`
+    pub fn json_stream_as<T: DeserializeOwned>(&mut self, format: JsonFormat) -> JsonStream<&mut Body, T> {
+        JsonStream::new(self.body_mut(), format)
+    }
+}
+
+/// the data without leading and trailing whitespace
+fn trim(data: &[u8]) -> &[u8] {
+    let start = data.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(data.len());
+    let end = data.iter().rposition(|b| !b.is_ascii_whitespace()).map(|end| end + 1).unwrap_or(start);
+    &data[start..end]
+}
+
+fn deserialize<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+    serde_json::from_slice(data).map_err(|e| Error::from(JsonError::new(e, Some(data))))
+}
+
+/// the error of item framing
+fn framing_error(msg: &str) -> Error {
+    Error::from(JsonError::new(serde_json::Error::custom(msg), None))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use serde::Deserialize;
+
+    use crate::body::Body;
+    use crate::client::tests::{local_client, serve_once};
+    use crate::request::Request;
+
+    use super::{JsonFormat, JsonStream};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Event {
+        id: u32,
+        msg: String,
+    }
+
+    /// a reader returning one byte each time, the items span many reads
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    fn events(data: &[u8], format: JsonFormat) -> Vec<Result<Event, String>> {
+        JsonStream::new(Trickle(data), format)
+            .map(|item| item.map_err(|e| e.to_string()))
+            .collect()
+    }
+
+    fn event(id: u32, msg: &str) -> Result<Event, String> {
+        Ok(Event { id, msg: msg.to_owned() })
+    }
+
+    #[test]
+    fn test_lines_and_sequence() {
+        let lines = b"{\"id\":1,\"msg\":\"a\\nb\"}\r\n\n  \n{\"id\":2,\"msg\":\"}\"}\nnot json\n{\"id\":3,\"msg\":\"last\"}";
+        let items = events(lines, JsonFormat::Lines);
+        assert_eq!(4, items.len());
+        assert_eq!(event(1, "a\nb"), items[0]);
+        assert_eq!(event(2, "}"), items[1]);
+        assert!(items[2].is_err());
+        assert_eq!(event(3, "last"), items[3]);
+
+        let seq = b"\x1e{\"id\":1,\"msg\":\"a\"}\n\x1e\n\x1e{\"id\":2,\n\"msg\":\"b\"}\n\x1e{\"id\":3\n\x1e{\"id\":4,\"msg\":\"d\"}\n";
+        let items = events(seq, JsonFormat::Sequence);
+        assert_eq!(4, items.len());
+        assert_eq!(event(1, "a"), items[0]);
+        assert_eq!(event(2, "b"), items[1]);
+        assert!(items[2].is_err());
+        assert_eq!(event(4, "d"), items[3]);
+        assert_eq!(1, events(b"{\"id\":1,\"msg\":\"a\"}\n", JsonFormat::Sequence).len());
+    }
+
+    #[test]
+    fn test_array() {
+        let array = b" [ {\"id\":1,\"msg\":\"[,]\\\"\"}, {\"id\":2,\"msg\":\"b\",\"tags\":[1,{\"x\":[]}]}\n] \n";
+        assert_eq!(vec![event(1, "[,]\""), event(2, "b")], events(array, JsonFormat::Array));
+        assert!(events(b"[]", JsonFormat::Array).is_empty());
+
+        let numbers: Vec<u64> = JsonStream::new(Trickle(b"[1, 20,300]"), JsonFormat::Array)
+            .collect::<crate::error::Result<_>>().unwrap();
+        assert_eq!(vec![1, 20, 300], numbers);
+
+        // the iteration ends after an error
+        let items = events(b"[{\"id\":1,\"msg\":\"a\"},{\"id\":2,", JsonFormat::Array);
+        assert_eq!(2, items.len());
+        assert!(items[1].is_err());
+        assert!(events(b"{\"id\":1,\"msg\":\"a\"}", JsonFormat::Array)[0].is_err());
+        assert!(events(b"[{\"id\":1,\"msg\":\"a\"} {}]", JsonFormat::Array)[0].is_err());
+        assert!(events(b"", JsonFormat::Array)[0].is_err());
+
+        let items: Vec<crate::error::Result<Event>> = JsonStream::new(Trickle(b"[{\"id\":1,\"msg\":\"too long\"}]"), JsonFormat::Array)
+            .max_item_size(8)
+            .collect();
+        assert_eq!(1, items.len());
+        assert!(items[0].is_err());
+    }
+
+    #[test]
+    fn test_streaming_response() {
+        let (url, server) = serve_once(b"HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nTransfer-Encoding: chunked\r\n\r\n\
+            11\r\n{\"id\":1,\"msg\":\"a\"\r\n\
+            15\r\n}\n{\"id\":2,\"msg\":\"b\"}\n\r\n\
+            0\r\n\r\n");
+        let req = Request::builder().uri(url).body(Body::empty()).unwrap();
+        let mut client = local_client();
+        let mut resp = client.send_streaming(req).unwrap();
+        let items: Vec<Event> = resp.json_stream().collect::<crate::error::Result<_>>().unwrap();
+        assert_eq!(vec![Event { id: 1, msg: "a".to_owned() }, Event { id: 2, msg: "b".to_owned() }], items);
+        drop(resp);
+        drop(client);
+        server.join().unwrap();
+    }
+}
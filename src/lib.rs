@@ -28,6 +28,9 @@ mod cookie;
 pub mod macros;
 mod proto;
 mod body;
+mod multipart;
+mod json;
+mod websocket;
 
 /// http configuration
 pub mod config {
@@ -35,6 +38,12 @@ pub mod config {
     pub mod h1 {
         pub use crate::proto::HttpConfig;
     }
+
+    /// for https
+    #[cfg(feature = "tls")]
+    pub mod tls {
+        pub use crate::proto::TlsConfig;
+    }
 }
 
 /// http headers
@@ -124,16 +133,24 @@ pub mod headers {
 pub mod produce {
     pub use url::{ParseError, Url};
 
-    pub use crate::body::{Body, BodyKind};
-    pub use crate::client::HttpClient;
+    pub use crate::body::{Body, BodyKind, BodyStream};
+    pub use crate::client::{ContentEncoding, DownloadOptions, HttpClient, NoProxy, NtlmAuth, Progress, Proxy, RedirectAction, RedirectAttempt, RedirectHistory, RedirectPolicy, RequestCoalescer};
+    pub use crate::cookie::{Cookie, CookieJar, CookieStore, SameSite};
     pub use crate::error::{Error, Result};
     pub use crate::extensions::Extensions;
+    pub use crate::json::{JsonFormat, JsonStream};
     pub use crate::method::Method;
-    pub use crate::proto::Connector;
+    pub use crate::multipart::{BodyPart, ContentRange, Multipart, Part};
+    pub use crate::proto::{Connector, HttpConnector, Socks5Connector, Stream};
+    #[cfg(any(feature = "gzip", feature = "deflate", feature = "zstd"))]
+    pub use crate::client::RequestCompression;
+    #[cfg(feature = "tls")]
+    pub use crate::proto::{MaybeTlsStream, TlsConnector};
     pub use crate::request::{Builder, Request};
     pub use crate::response::Response;
     pub use crate::status::StatusCode;
     pub use crate::version::Version;
+    pub use crate::websocket::{CloseFrame, Message, WebSocket};
 }
 
 
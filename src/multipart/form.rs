@@ -0,0 +1,355 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+use rand::distr::Alphanumeric;
+use rand::Rng;
+
+use crate::body::Body;
+use crate::error::Result;
+use crate::header::{HeaderMap, HeaderName, HeaderValue};
+
+/// the length of generated boundary
+const BOUNDARY_LENGTH: usize = 32;
+
+/// the `multipart/form-data` body builder
+///
+/// ```no_run
+/// use request_rs::headers::HeaderValue;
+/// use request_rs::produce::*;
+///
+/// let form = Multipart::new()
+///     .text("title", "report")
+///     .file("attachment", "report.pdf").unwrap()
+///     .part("meta", Part::bytes(b"{}".to_vec()).content_type(HeaderValue::from_static("application/json")));
+/// let mut client = HttpClient::http();
+/// let resp = client.send_request("http://www.example.com/upload", Method::POST, None, Some(form.into_body())).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Multipart {
+    boundary: String,
+    fields: Vec<(String, Part)>,
+}
+
+impl Multipart {
+    /// Construct a new Multipart with a random boundary
+    pub fn new() -> Self {
+        let boundary: String = rand::rng()
+            .sample_iter(Alphanumeric)
+            .take(BOUNDARY_LENGTH)
+            .map(char::from)
+            .collect();
+        Self {
+            boundary: format!("------------------------{}", boundary),
+            fields: Vec::new(),
+        }
+    }
+
+    /// the boundary between parts
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// add a text field
+    pub fn text<V: Into<String>>(self, name: &str, value: V) -> Self {
+        self.part(name, Part::text(value))
+    }
+
+    /// add a file field, the file is read while sending,
+    /// the file name of `path` is used as `filename`
+    pub fn file<P: AsRef<Path>>(self, name: &str, path: P) -> Result<Self> {
+        Ok(self.part(name, Part::file(path)?))
+    }
+
+    /// add a file field read from `reader`, the length of body is unknown
+    pub fn reader<R: Read + Send + 'static>(self, name: &str, reader: R, filename: &str) -> Self {
+        self.part(name, Part::reader(reader).file_name(filename))
+    }
+
+    /// add a custom part
+    pub fn part(mut self, name: &str, part: Part) -> Self {
+        self.fields.push((name.to_owned(), part));
+        self
+    }
+
+    /// the `Content-Type` with boundary
+    pub fn content_type(&self) -> HeaderValue {
+        HeaderValue::from_str(&format!("multipart/form-data; boundary={}", self.boundary))
+            .expect("the boundary is alphanumeric")
+    }
+
+    /// the length of encoded body, `None` if any part has unknown length
+    pub fn content_length(&self) -> Option<u64> {
+        let mut length = 0;
+        for (name, part) in &self.fields {
+            length += self.part_head(name, part).len() as u64 + part.length()? + 2;
+        }
+        Some(length + self.boundary.len() as u64 + 6)
+    }
+
+    /// Create the Request Body, the body is in memory if all parts are in memory,
+    /// otherwise it's streamed with `Content-Length` if all parts have known sizes,
+    /// or with chunked encoding
+    pub fn into_body(self) -> Body {
+        let content_type = self.content_type();
+        let length = self.content_length();
+        let streaming = self.fields.iter().any(|(_, part)| part.is_stream());
+        let tail = format!("--{}--\r\n", self.boundary);
+        let body = if !streaming {
+            let mut data = Vec::new();
+            for (name, part) in self.fields.iter() {
+                data.extend_from_slice(self.part_head(name, part).as_bytes());
+                if let PartData::Bytes(bytes) = &part.data {
+                    data.extend_from_slice(bytes);
+                }
+                data.extend_from_slice(b"\r\n");
+            }
+            data.extend_from_slice(tail.as_bytes());
+            Body::from_vec(data)
+        } else {
+            let mut readers: VecDeque<Box<dyn Read + Send>> = VecDeque::new();
+            let heads: Vec<String> = self.fields.iter().map(|(name, part)| self.part_head(name, part)).collect();
+            for ((_, part), head) in self.fields.into_iter().zip(heads) {
+                readers.push_back(Box::new(Cursor::new(head.into_bytes())));
+                match part.data {
+                    PartData::Bytes(bytes) => readers.push_back(Box::new(Cursor::new(bytes))),
+                    PartData::Stream(reader, _) => readers.push_back(reader),
+                }
+                readers.push_back(Box::new(Cursor::new(b"\r\n")));
+            }
+            readers.push_back(Box::new(Cursor::new(tail.into_bytes())));
+            let reader = ChainReader { readers };
+            match length {
+                Some(length) => Body::from_sized_reader(reader, length),
+                None => Body::from_reader(reader),
+            }
+        };
+        body.with_content_type(content_type)
+    }
+
+    /// the boundary line and headers of `part`
+    fn part_head(&self, name: &str, part: &Part) -> String {
+        let mut head = format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"", self.boundary, escape(name));
+        if let Some(filename) = &part.filename {
+            head.push_str(&format!("; filename=\"{}\"", escape(filename)));
+        }
+        head.push_str("\r\n");
+        if let Some(content_type) = &part.content_type {
+            head.push_str("Content-Type: ");
+            head.push_str(&String::from_utf8_lossy(content_type.as_bytes()));
+            head.push_str("\r\n");
+        }
+        for (key, value) in part.headers.iter() {
+            head.push_str(key.as_str());
+            head.push_str(": ");
+            head.push_str(&String::from_utf8_lossy(value.as_bytes()));
+            head.push_str("\r\n");
+        }
+        head.push_str("\r\n");
+        head
+    }
+}
+
+impl Default for Multipart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// escape the field name and file name as html5 does,
+/// `"`, `\r` and `\n` are percent-encoded
+fn escape(value: &str) -> String {
+    value.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// the data of a part
+enum PartData {
+    Bytes(Vec<u8>),
+    Stream(Box<dyn Read + Send>, Option<u64>),
+}
+
+/// a part of `multipart/form-data` body
+pub struct Part {
+    data: PartData,
+    filename: Option<String>,
+    content_type: Option<HeaderValue>,
+    headers: HeaderMap,
+}
+
+impl Part {
+    fn new(data: PartData) -> Self {
+        Self {
+            data,
+            filename: None,
+            content_type: None,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// the part of text
+    pub fn text<V: Into<String>>(value: V) -> Self {
+        Self::new(PartData::Bytes(value.into().into_bytes()))
+    }
+
+    /// the part of binary data
+    pub fn bytes(data: Vec<u8>) -> Self {
+        Self::new(PartData::Bytes(data))
+    }
+
+    /// the part read from `reader` while sending, the length is unknown
+    pub fn reader<R: Read + Send + 'static>(reader: R) -> Self {
+        Self::new(PartData::Stream(Box::new(reader), None))
+    }
+
+    /// the part read from `reader` while sending, the reader should yield `length` bytes
+    pub fn sized_reader<R: Read + Send + 'static>(reader: R, length: u64) -> Self {
+        Self::new(PartData::Stream(Box::new(reader), Some(length)))
+    }
+
+    /// the part read from file while sending, the file name of `path` is used as `filename`,
+    /// the content type is `application/octet-stream`
+    pub fn file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let part = Self::sized_reader(file, length)
+            .content_type(HeaderValue::from_static("application/octet-stream"));
+        Ok(match path.file_name() {
+            Some(filename) => part.file_name(&filename.to_string_lossy()),
+            None => part,
+        })
+    }
+
+    /// set the `filename` of `Content-Disposition`
+    pub fn file_name(mut self, filename: &str) -> Self {
+        self.filename = Some(filename.to_owned());
+        self
+    }
+
+    /// set the `Content-Type` of the part
+    pub fn content_type(mut self, content_type: HeaderValue) -> Self {
+        self.content_type = Some(content_type);
+        self
+    }
+
+    /// add a header to the part, `Content-Disposition` and `Content-Type` are set by the builder
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// the length of part data, `None` if unknown
+    fn length(&self) -> Option<u64> {
+        match &self.data {
+            PartData::Bytes(bytes) => Some(bytes.len() as u64),
+            PartData::Stream(_, length) => *length,
+        }
+    }
+
+    fn is_stream(&self) -> bool {
+        match self.data {
+            PartData::Stream(..) => true,
+            PartData::Bytes(_) => false,
+        }
+    }
+}
+
+impl fmt::Debug for Part {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Part")
+            .field("filename", &self.filename)
+            .field("content_type", &self.content_type)
+            .field("headers", &self.headers)
+            .field("length", &self.length())
+            .finish()
+    }
+}
+
+/// read the readers one by one
+struct ChainReader {
+    readers: VecDeque<Box<dyn Read + Send>>,
+}
+
+impl Read for ChainReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(reader) = self.readers.front_mut() {
+            let n = reader.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            self.readers.pop_front();
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use crate::body::{Body, BodyKind};
+    use crate::header::{HeaderName, HeaderValue};
+
+    use super::{Multipart, Part};
+
+    fn read_body(mut body: Body) -> (Option<u64>, Vec<u8>) {
+        let length = body.content_length();
+        let mut data = Vec::new();
+        body.read_to_end(&mut data).unwrap();
+        (length, data)
+    }
+
+    #[test]
+    fn test_in_memory_form() {
+        let form = Multipart::new()
+            .text("title", "hello")
+            .part("data", Part::bytes(vec![1, 2, 3])
+                .file_name("a\"b\r\n.bin")
+                .content_type(HeaderValue::from_static("application/x-raw"))
+                .header(HeaderName::from_static("x-checksum"), HeaderValue::from_static("abc")));
+        let boundary = form.boundary().to_owned();
+        let content_type = form.content_type();
+        assert_eq!(format!("multipart/form-data; boundary={}", boundary), content_type.to_str().unwrap());
+        let expected_length = form.content_length();
+        let body = form.into_body();
+        assert_eq!(Some(&content_type), body.content_type());
+        match body.kind() {
+            BodyKind::Binary(_) => {}
+            _ => panic!("body should be binary"),
+        }
+        let (length, data) = read_body(body);
+        let expected = format!("--{b}\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nhello\r\n\
+            --{b}\r\nContent-Disposition: form-data; name=\"data\"; filename=\"a%22b%0D%0A.bin\"\r\n\
+            Content-Type: application/x-raw\r\nx-checksum: abc\r\n\r\n", b = boundary);
+        let mut expected = expected.into_bytes();
+        expected.extend_from_slice(&[1, 2, 3]);
+        expected.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+        assert_eq!(expected, data);
+        assert_eq!(Some(data.len() as u64), length);
+        assert_eq!(expected_length, length);
+    }
+
+    #[test]
+    fn test_streamed_form() {
+        let form = Multipart::new()
+            .text("title", "hello")
+            .part("file", Part::sized_reader(Cursor::new(vec![b'x'; 10000]), 10000).file_name("x.txt"));
+        let (length, data) = read_body(form.into_body());
+        assert_eq!(Some(data.len() as u64), length);
+        assert!(data.windows(10000).any(|w| w == &[b'x'; 10000][..]));
+
+        let form = Multipart::new().reader("file", Cursor::new(b"abc".to_vec()), "abc.txt");
+        let boundary = form.boundary().to_owned();
+        let (length, data) = read_body(form.into_body());
+        assert_eq!(None, length);
+        let expected = format!("--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"abc.txt\"\r\n\r\nabc\r\n--{b}--\r\n", b = boundary);
+        assert_eq!(expected.into_bytes(), data);
+    }
+
+    #[test]
+    fn test_random_boundary() {
+        assert_ne!(Multipart::new().boundary(), Multipart::new().boundary());
+    }
+}
@@ -0,0 +1,5 @@
+pub use self::form::{Multipart, Part};
+pub use self::parse::{BodyPart, ContentRange};
+
+mod form;
+mod parse;
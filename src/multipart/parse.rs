@@ -0,0 +1,284 @@
+use std::str::FromStr;
+
+use crate::body::Body;
+use crate::error::{Error, InvalidHttpHeader, MultipartError, Result};
+use crate::header::{CONTENT_RANGE, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use crate::response::Response;
+
+/// the max number of headers in a part
+const MAX_HEADERS: usize = 64;
+
+/// the value of `Content-Range: bytes first-last/complete-length`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentRange {
+    range: Option<(u64, u64)>,
+    complete_length: Option<u64>,
+}
+
+impl ContentRange {
+    /// the first byte position, `None` for unsatisfied range `bytes */complete-length`
+    pub fn start(&self) -> Option<u64> {
+        self.range.map(|(start, _)| start)
+    }
+
+    /// the last byte position, inclusive
+    pub fn end(&self) -> Option<u64> {
+        self.range.map(|(_, end)| end)
+    }
+
+    /// the length of whole representation, `None` if unknown
+    pub fn complete_length(&self) -> Option<u64> {
+        self.complete_length
+    }
+
+    /// parse the `Content-Range` header of `headers`
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        headers.get(CONTENT_RANGE)?.to_str().ok()?.parse().ok()
+    }
+}
+
+impl FromStr for ContentRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::from(InvalidHttpHeader::new(&format!("invalid Content-Range: {}", s)));
+        let range = s.trim().strip_prefix("bytes ").ok_or_else(invalid)?;
+        let mut parts = range.splitn(2, '/');
+        let positions = parts.next().ok_or_else(invalid)?.trim();
+        let complete_length = match parts.next().ok_or_else(invalid)?.trim() {
+            "*" => None,
+            length => Some(length.parse::<u64>().map_err(|_| invalid())?),
+        };
+        let range = if positions == "*" {
+            None
+        } else {
+            let mut positions = positions.splitn(2, '-');
+            let start = positions.next().and_then(|p| p.trim().parse::<u64>().ok()).ok_or_else(invalid)?;
+            let end = positions.next().and_then(|p| p.trim().parse::<u64>().ok()).ok_or_else(invalid)?;
+            if end < start {
+                return Err(invalid());
+            }
+            Some((start, end))
+        };
+        if range.is_none() && complete_length.is_none() {
+            return Err(invalid());
+        }
+        Ok(Self { range, complete_length })
+    }
+}
+
+/// a part of `multipart/*` response body
+#[derive(Debug, Clone)]
+pub struct BodyPart {
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl BodyPart {
+    /// the headers of the part
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// the body of the part
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// the `Content-Type` of the part
+    pub fn content_type(&self) -> Option<&HeaderValue> {
+        self.headers.get(CONTENT_TYPE)
+    }
+
+    /// the `Content-Range` of the part, the range of the representation in the body
+    /// for `multipart/byteranges`
+    pub fn content_range(&self) -> Option<ContentRange> {
+        ContentRange::from_headers(&self.headers)
+    }
+}
+
+impl Response<Body> {
+    /// split the `multipart/*` response body such as `multipart/mixed` and `multipart/byteranges`
+    /// into parts, the preamble and epilogue are ignored.
+    /// the stream body of `HttpClient::send_streaming` is consumed
+    ///
+    /// ```no_run
+    /// use request_rs::headers::{HeaderMap, RANGE};
+    /// use request_rs::produce::*;
+    ///
+    /// let mut client = HttpClient::http();
+    /// let mut headers = HeaderMap::new();
+    /// headers.insert(RANGE, "bytes=0-99,200-299".parse().unwrap());
+    /// let resp = client.send_request("http://www.example.com/file", Method::GET, Some(headers), None).unwrap();
+    /// for part in resp.multipart().unwrap() {
+    ///     let range = part.content_range().unwrap();
+    ///     println!("{:?}-{:?}: {} bytes", range.start(), range.end(), part.body().len());
+    /// }
+    /// ```
+    pub fn multipart(&self) -> Result<Vec<BodyPart>> {
+        let content_type = self.headers().get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| Error::from(MultipartError::new("the response has no Content-Type")))?;
+        let boundary = boundary(content_type)
+            .ok_or_else(|| Error::from(MultipartError::new(&format!("not a multipart response: {}", content_type))))?;
+        let data = self.body().to_vec()?;
+        parse(&data, &boundary)
+    }
+}
+
+/// the `boundary` parameter of `multipart/*` media type
+pub(crate) fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    if !params.next()?.trim().to_ascii_lowercase().starts_with("multipart/") {
+        return None;
+    }
+    params.find_map(|param| {
+        let mut pair = param.splitn(2, '=');
+        if !pair.next()?.trim().eq_ignore_ascii_case("boundary") {
+            return None;
+        }
+        let value = pair.next()?.trim().trim_matches('"');
+        if value.is_empty() {
+            None
+        } else {
+            Some(value.to_owned())
+        }
+    })
+}
+
+fn find(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    data.windows(pattern.len()).position(|window| window == pattern)
+}
+
+/// split `data` into parts by `boundary`
+pub(crate) fn parse(data: &[u8], boundary: &str) -> Result<Vec<BodyPart>> {
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+    // the first delimiter may be at the beginning of body without the leading CRLF
+    let mut pos = if data.starts_with(&delimiter[2..]) {
+        delimiter.len() - 2
+    } else {
+        find(data, &delimiter)
+            .map(|index| index + delimiter.len())
+            .ok_or_else(|| Error::from(MultipartError::new("the boundary is not found in body")))?
+    };
+    let mut parts = Vec::new();
+    loop {
+        if data[pos..].starts_with(b"--") {
+            return Ok(parts);
+        }
+        // skip the transport padding after the boundary
+        let line_end = find(&data[pos..], b"\r\n")
+            .ok_or_else(|| Error::from(MultipartError::new("unexpected end of multipart body")))?;
+        pos += line_end + 2;
+        let part_end = find(&data[pos..], &delimiter)
+            .ok_or_else(|| Error::from(MultipartError::new("the close delimiter is not found in body")))?;
+        parts.push(parse_part(&data[pos..pos + part_end])?);
+        pos += part_end + delimiter.len();
+    }
+}
+
+/// parse the headers and body of a part
+fn parse_part(data: &[u8]) -> Result<BodyPart> {
+    if data.starts_with(b"\r\n") {
+        return Ok(BodyPart { headers: HeaderMap::new(), body: data[2..].to_vec() });
+    }
+    let mut raw = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let (len, raw) = match httparse::parse_headers(data, &mut raw) {
+        Ok(httparse::Status::Complete(parsed)) => parsed,
+        Ok(httparse::Status::Partial) => return Err(Error::from(MultipartError::new("incomplete headers of part"))),
+        Err(e) => return Err(Error::from(MultipartError::new(&format!("invalid headers of part: {}", e)))),
+    };
+    let mut headers = HeaderMap::with_capacity(raw.len());
+    for header in raw {
+        headers.append(HeaderName::from_bytes(header.name.as_bytes())?, HeaderValue::from_bytes(header.value)?);
+    }
+    Ok(BodyPart { headers, body: data[len..].to_vec() })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::Body;
+    use crate::error::InvalidHttpHeader;
+    use crate::header::CONTENT_TYPE;
+    use crate::response::Response;
+
+    use super::{boundary, ContentRange};
+
+    fn response(content_type: &str, body: &str) -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from_str(body)).unwrap()
+    }
+
+    #[test]
+    fn test_boundary() {
+        assert_eq!(Some("abc".to_owned()), boundary("multipart/mixed; boundary=abc"));
+        assert_eq!(Some("a b".to_owned()), boundary("Multipart/Byteranges; charset=utf-8; BOUNDARY=\"a b\""));
+        assert_eq!(None, boundary("text/plain; boundary=abc"));
+        assert_eq!(None, boundary("multipart/mixed"));
+    }
+
+    #[test]
+    fn test_content_range() {
+        let range: ContentRange = "bytes 0-99/1234".parse().unwrap();
+        assert_eq!((Some(0), Some(99), Some(1234)), (range.start(), range.end(), range.complete_length()));
+        let range: ContentRange = "bytes 100-199/*".parse().unwrap();
+        assert_eq!((Some(100), None), (range.start(), range.complete_length()));
+        let range: ContentRange = "bytes */1234".parse().unwrap();
+        assert_eq!((None, Some(1234)), (range.start(), range.complete_length()));
+        assert!("bytes 10-5/100".parse::<ContentRange>().is_err());
+        assert!("bytes */*".parse::<ContentRange>().unwrap_err().is::<InvalidHttpHeader>());
+    }
+
+    #[test]
+    fn test_byteranges() {
+        let resp = response("multipart/byteranges; boundary=THIS_STRING_SEPARATES",
+                            "--THIS_STRING_SEPARATES\r\n\
+                             Content-Type: text/plain\r\n\
+                             Content-Range: bytes 0-4/20\r\n\
+                             \r\n\
+                             hello\r\n\
+                             --THIS_STRING_SEPARATES\r\n\
+                             Content-Type: text/plain\r\n\
+                             Content-Range: bytes 15-19/20\r\n\
+                             \r\n\
+                             world\r\n\
+                             --THIS_STRING_SEPARATES--\r\n");
+        let parts = resp.multipart().unwrap();
+        assert_eq!(2, parts.len());
+        assert_eq!(b"hello", parts[0].body());
+        assert_eq!(Some(0), parts[0].content_range().unwrap().start());
+        assert_eq!(b"world", parts[1].body());
+        assert_eq!(Some(15), parts[1].content_range().unwrap().start());
+        assert_eq!(Some(19), parts[1].content_range().unwrap().end());
+        assert_eq!("text/plain", parts[1].content_type().unwrap());
+    }
+
+    #[test]
+    fn test_mixed_with_preamble() {
+        let resp = response("multipart/mixed; boundary=\"batch\"",
+                            "this is preamble\r\n\
+                             --batch  \r\n\
+                             \r\n\
+                             no headers\r\n\
+                             --batch\r\n\
+                             Content-Type: application/json\r\n\
+                             \r\n\
+                             {\"id\": 1}\r\n\r\n\
+                             --batch--\r\n\
+                             epilogue");
+        let parts = resp.multipart().unwrap();
+        assert_eq!(2, parts.len());
+        assert!(parts[0].headers().is_empty());
+        assert_eq!(b"no headers", parts[0].body());
+        assert_eq!(b"{\"id\": 1}\r\n", parts[1].body());
+        assert_eq!("application/json", parts[1].content_type().unwrap());
+    }
+
+    #[test]
+    fn test_invalid_multipart() {
+        assert!(response("text/plain", "--a\r\n\r\nx\r\n--a--").multipart().is_err());
+        assert!(response("multipart/mixed; boundary=a", "--a\r\n\r\nx").multipart().is_err());
+        assert!(response("multipart/mixed; boundary=a", "no boundary").multipart().is_err());
+    }
+}
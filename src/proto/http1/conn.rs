@@ -7,8 +7,8 @@ use net2::{TcpBuilder, TcpStreamExt};
 
 use crate::body::{Body, BodyKind};
 use crate::body_kind;
-use crate::error::Result;
-use crate::proto::Connector;
+use crate::error::{BodyError, Error, Result};
+use crate::proto::{Connector, Stream};
 
 /// the tcp configuration for http client
 #[derive(Debug, Clone)]
@@ -118,6 +118,10 @@ impl HttpConnector {
             },
             empty => {
 
+            },
+            stream => {
+                let mut reader = stream.take().ok_or(Error::from(BodyError::new("the body stream has been sent, it can't be sent again")))?;
+                std::io::copy(&mut reader, self)?;
             }
         );
         Ok(())
@@ -215,30 +219,34 @@ impl HttpConnector {
 }
 
 
+/// open a tcp stream to `socket_addr` use given http config
+pub(crate) fn tcp_connect(config: &HttpConfig, socket_addr: &SocketAddr) -> Result<TcpStream> {
+    // use net2 crate to build Tcp Stream
+    let tcp_builder = match socket_addr {
+        SocketAddr::V4(_) => TcpBuilder::new_v4(),
+        SocketAddr::V6(_) => TcpBuilder::new_v6(),
+    }?;
+    //  Set value for the `SO_REUSEADDR` option on this socket
+    if config.reuse_address {
+        tcp_builder.reuse_address(true)?;
+    }
+    // ttl
+    tcp_builder.ttl(config.ttl)?;
+    if let Some(ref local) = config.local_address {
+        // let system chose port
+        tcp_builder.bind(SocketAddr::new(*local, 0))?;
+    }
+    let stream = tcp_builder.connect(socket_addr)?;
+    stream.set_write_timeout(config.connect_timeout)?;
+    stream.set_read_timeout(config.connect_timeout)?;
+    stream.set_nodelay(config.nodelay)?;
+    stream.set_keepalive(config.keep_alive_timeout)?;
+    Ok(stream)
+}
+
 impl Connector for HttpConnector {
     fn create_connection(&mut self, socket_addr: &SocketAddr) -> Result<TcpStream> {
-        let config = self.config();
-        // use net2 crate to build Tcp Stream
-        let tcp_builder = match socket_addr {
-            SocketAddr::V4(_) => TcpBuilder::new_v4(),
-            SocketAddr::V6(_) => TcpBuilder::new_v6(),
-        }?;
-        //  Set value for the `SO_REUSEADDR` option on this socket
-        if config.reuse_address {
-            tcp_builder.reuse_address(true)?;
-        }
-        // ttl
-        tcp_builder.ttl(config.ttl)?;
-        if let Some(ref local) = config.local_address {
-            // let system chose port
-            tcp_builder.bind(SocketAddr::new(local.clone(), 0))?;
-        }
-        let stream = tcp_builder.connect(socket_addr)?;
-        stream.set_write_timeout(config.connect_timeout.clone())?;
-        stream.set_read_timeout(config.connect_timeout.clone())?;
-        stream.set_nodelay(config.nodelay)?;
-        stream.set_keepalive(config.keep_alive_timeout.clone())?;
-        Ok(stream)
+        tcp_connect(self.config(), socket_addr)
     }
 
     fn connect_to(&mut self, addr: &SocketAddr) -> Result<()> {
@@ -246,6 +254,17 @@ impl Connector for HttpConnector {
         self.stream = Some(stream);
         Ok(())
     }
+
+    fn take_stream(&mut self) -> Option<Box<dyn Stream>> {
+        self.stream.take().map(|stream| Box::new(stream) as Box<dyn Stream>)
+    }
+
+    fn set_stream(&mut self, stream: Box<dyn Stream>) {
+        match stream.into_any().downcast::<TcpStream>() {
+            Ok(stream) => self.stream = Some(*stream),
+            Err(_) => debug!("the connection is not opened by HttpConnector"),
+        }
+    }
 }
 
 impl Read for HttpConnector {
@@ -0,0 +1,197 @@
+use std::io::ErrorKind;
+
+use bytes::{Buf, BytesMut};
+
+use crate::error::{Error, InvalidHttpHeader, IoError, Result};
+use crate::header::{CONTENT_LENGTH, HeaderMap, TRANSFER_ENCODING};
+use crate::method::Method;
+use crate::status::StatusCode;
+
+/// Decoders to handle different Transfer-Encodings.
+///
+/// The decoder is fed with the bytes received from the connection,
+/// decoded body data is appended to the output buffer.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Decoder {
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    /// A Reader used when a Content-Length header is passed with a positive integer.
+    Length(u64),
+    /// A Reader used when Transfer-Encoding is `chunked`.
+    Chunked(ChunkedState, u64),
+    /// A Reader used for responses that don't indicate a length or chunked.
+    ///
+    /// The bool tracks when EOF is seen on the transport.
+    Eof(bool),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ChunkedState {
+    Size,
+    SizeLws,
+    Extension,
+    SizeLf,
+    Body,
+    BodyCr,
+    BodyLf,
+    Trailer,
+    TrailerLf,
+    EndCr,
+    EndLf,
+    End,
+}
+
+impl Decoder {
+    /// body with known length
+    pub fn length(len: u64) -> Self {
+        Self { kind: Kind::Length(len) }
+    }
+
+    /// body encoded by `Transfer-Encoding: chunked`
+    pub fn chunked() -> Self {
+        Self { kind: Kind::Chunked(ChunkedState::Size, 0) }
+    }
+
+    /// body delimited by closing the connection
+    pub fn eof() -> Self {
+        Self { kind: Kind::Eof(false) }
+    }
+
+    /// determine the body length of response as RFC 7230 section 3.3.3
+    pub fn for_response(method: &Method, status: StatusCode, headers: &HeaderMap) -> Result<Self> {
+        if *method == Method::HEAD
+            || status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED {
+            return Ok(Self::length(0));
+        }
+        if *method == Method::CONNECT && status.is_success() {
+            return Ok(Self::length(0));
+        }
+        if headers.contains_key(TRANSFER_ENCODING) {
+            // chunked must be the final encoding
+            let chunked = headers.get_all(TRANSFER_ENCODING).iter()
+                .next_back()
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .map(|encoding| encoding.trim().eq_ignore_ascii_case("chunked"))
+                .unwrap_or(false);
+            return if chunked { Ok(Self::chunked()) } else { Ok(Self::eof()) };
+        }
+        let mut length = None;
+        for value in headers.get_all(CONTENT_LENGTH) {
+            let len = value.to_str().ok()
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .ok_or(Error::from(InvalidHttpHeader::new("invalid Content-Length")))?;
+            match length {
+                Some(prev) if prev != len => {
+                    return Err(Error::from(InvalidHttpHeader::new("conflicting Content-Length")));
+                }
+                _ => length = Some(len),
+            }
+        }
+        match length {
+            Some(len) => Ok(Self::length(len)),
+            None => Ok(Self::eof()),
+        }
+    }
+
+    /// return true if the body is delimited by closing the connection
+    pub fn is_eof(&self) -> bool {
+        matches!(self.kind, Kind::Eof(_))
+    }
+
+    /// decode the bytes in `buf` and append body data to `out`,
+    /// return true once the whole body is decoded,
+    /// bytes after the end of body are left in `buf`
+    pub fn decode(&mut self, buf: &mut BytesMut, out: &mut BytesMut) -> Result<bool> {
+        match self.kind {
+            Kind::Length(ref mut remaining) => {
+                let n = std::cmp::min(*remaining, buf.len() as u64) as usize;
+                out.extend_from_slice(&buf.split_to(n));
+                *remaining -= n as u64;
+                Ok(*remaining == 0)
+            }
+            Kind::Chunked(ref mut state, ref mut size) => {
+                loop {
+                    match *state {
+                        ChunkedState::End => return Ok(true),
+                        ChunkedState::Body => {
+                            if buf.is_empty() {
+                                return Ok(false);
+                            }
+                            let n = std::cmp::min(*size, buf.len() as u64) as usize;
+                            out.extend_from_slice(&buf.split_to(n));
+                            *size -= n as u64;
+                            if *size == 0 {
+                                *state = ChunkedState::BodyCr;
+                            }
+                        }
+                        _ => {
+                            if buf.is_empty() {
+                                return Ok(false);
+                            }
+                            let byte = buf[0];
+                            buf.advance(1);
+                            *state = state.step(byte, size)?;
+                        }
+                    }
+                }
+            }
+            Kind::Eof(finished) => {
+                out.extend_from_slice(&buf.split());
+                Ok(finished)
+            }
+        }
+    }
+
+    /// the connection was closed, return error if the body is incomplete
+    pub fn decode_eof(&mut self) -> Result<()> {
+        match self.kind {
+            Kind::Eof(ref mut finished) => {
+                *finished = true;
+                Ok(())
+            }
+            Kind::Length(0) | Kind::Chunked(ChunkedState::End, _) => Ok(()),
+            _ => Err(Error::from(IoError::from_kind(ErrorKind::UnexpectedEof))),
+        }
+    }
+}
+
+impl ChunkedState {
+    fn step(self, byte: u8, size: &mut u64) -> Result<ChunkedState> {
+        use self::ChunkedState::*;
+        let state = match (self, byte) {
+            (Size, b'0'..=b'9') | (Size, b'a'..=b'f') | (Size, b'A'..=b'F') => {
+                let digit = (byte as char).to_digit(16).unwrap() as u64;
+                *size = size.checked_mul(16)
+                    .and_then(|size| size.checked_add(digit))
+                    .ok_or_else(invalid_chunk)?;
+                Size
+            }
+            (Size, b'\t') | (Size, b' ') | (SizeLws, b'\t') | (SizeLws, b' ') => SizeLws,
+            (Size, b';') | (SizeLws, b';') => Extension,
+            (Size, b'\r') | (SizeLws, b'\r') | (Extension, b'\r') => SizeLf,
+            (Extension, _) => Extension,
+            (SizeLf, b'\n') if *size == 0 => EndCr,
+            (SizeLf, b'\n') => Body,
+            (BodyCr, b'\r') => BodyLf,
+            (BodyLf, b'\n') => Size,
+            (Trailer, b'\r') => TrailerLf,
+            (Trailer, _) => Trailer,
+            (TrailerLf, b'\n') => EndCr,
+            (EndCr, b'\r') => EndLf,
+            (EndCr, _) => Trailer,
+            (EndLf, b'\n') => End,
+            _ => return Err(invalid_chunk()),
+        };
+        Ok(state)
+    }
+}
+
+fn invalid_chunk() -> Error {
+    Error::from(IoError::from_kind(ErrorKind::InvalidData))
+}
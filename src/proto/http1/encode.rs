@@ -0,0 +1,153 @@
+use std::io::{ErrorKind, Read, Write};
+
+use crate::body::BodyStream;
+use crate::error::{BodyError, Error, InvalidHttpHeader, Result};
+use crate::header::{CONTENT_LENGTH, HeaderMap, TRANSFER_ENCODING};
+
+/// the maximum size of data read from the body stream at once
+const CHUNK_SIZE: usize = 8192;
+
+/// the length of body stream sent with given request headers,
+/// `None` if the body is sent with chunked encoding.
+/// `Content-Length` set by user takes precedence over the length of stream
+pub(crate) fn stream_length(headers: &HeaderMap, stream: &BodyStream) -> Result<Option<u64>> {
+    if headers.contains_key(TRANSFER_ENCODING) {
+        return Ok(None);
+    }
+    match headers.get(CONTENT_LENGTH) {
+        Some(value) => {
+            let len = value.to_str()?.trim().parse::<u64>()
+                .map_err(|_| Error::from(InvalidHttpHeader::new("invalid Content-Length")))?;
+            Ok(Some(len))
+        }
+        None => Ok(stream.length()),
+    }
+}
+
+/// write the body stream, exactly `content_length` bytes are written if the length is known,
+/// otherwise the body is sent with chunked encoding
+pub(crate) fn write_stream<W: Write>(stream: &BodyStream, content_length: Option<u64>, writer: &mut W) -> Result<()> {
+    match content_length {
+        Some(len) => write_length(stream, len, writer),
+        None => write_chunked(stream, writer),
+    }
+}
+
+/// copy `len` bytes from the body stream in bounded chunks
+fn write_length<W: Write>(stream: &BodyStream, len: u64, writer: &mut W) -> Result<()> {
+    let mut reader = take_reader(stream)?.take(len);
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut written = 0;
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Error::from(e)),
+        };
+        writer.write_all(&buf[..n])?;
+        written += n as u64;
+    }
+    if written < len {
+        return Err(Error::from(BodyError::new(&format!(
+            "the body stream ended after {} bytes, but Content-Length is {}", written, len))));
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// write the body stream as `Transfer-Encoding: chunked`, followed by the trailers
+fn write_chunked<W: Write>(stream: &BodyStream, writer: &mut W) -> Result<()> {
+    let mut reader = take_reader(stream)?;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut frame = Vec::with_capacity(CHUNK_SIZE + 12);
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Error::from(e)),
+        };
+        frame.clear();
+        write!(frame, "{:X}\r\n", n)?;
+        frame.extend_from_slice(&buf[..n]);
+        frame.extend_from_slice(b"\r\n");
+        writer.write_all(&frame)?;
+    }
+    frame.clear();
+    frame.extend_from_slice(b"0\r\n");
+    if let Some(trailers) = stream.trailers() {
+        for (name, value) in trailers {
+            frame.extend_from_slice(name.as_str().as_bytes());
+            frame.extend_from_slice(b": ");
+            frame.extend_from_slice(value.as_bytes());
+            frame.extend_from_slice(b"\r\n");
+        }
+    }
+    frame.extend_from_slice(b"\r\n");
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn take_reader(stream: &BodyStream) -> Result<Box<dyn Read + Send>> {
+    stream.take().ok_or(Error::from(BodyError::new("the body stream has been sent, it can't be sent again")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use crate::body::BodyStream;
+    use crate::error::BodyError;
+    use crate::header::HeaderMap;
+
+    use super::{stream_length, write_stream};
+
+    /// yield at most 3 bytes each read
+    struct Slow(Cursor<Vec<u8>>);
+
+    impl Read for Slow {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = std::cmp::min(3, buf.len());
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_write_chunked_with_trailers() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("checksum", "abc".parse().unwrap());
+        let stream = BodyStream::new(Slow(Cursor::new(b"hello".to_vec()))).with_trailers(trailers);
+        let mut out = Vec::new();
+        write_stream(&stream, None, &mut out).unwrap();
+        assert_eq!(b"3\r\nhel\r\n2\r\nlo\r\n0\r\nchecksum: abc\r\n\r\n".to_vec(), out);
+        // the stream can only be sent once
+        let err = write_stream(&stream.clone(), None, &mut Vec::new()).unwrap_err();
+        assert!(err.is::<BodyError>());
+    }
+
+    #[test]
+    fn test_stream_length() {
+        let stream = BodyStream::new(Cursor::new(b"hello".to_vec()));
+        let mut headers = HeaderMap::new();
+        assert_eq!(None, stream_length(&headers, &stream).unwrap());
+        let stream = stream.with_length(5);
+        assert_eq!(Some(5), stream_length(&headers, &stream).unwrap());
+        headers.insert("content-length", "3".parse().unwrap());
+        assert_eq!(Some(3), stream_length(&headers, &stream).unwrap());
+        headers.insert("transfer-encoding", "chunked".parse().unwrap());
+        assert_eq!(None, stream_length(&headers, &stream).unwrap());
+    }
+
+    #[test]
+    fn test_write_length() {
+        let stream = BodyStream::new(Cursor::new(b"hello world".to_vec()));
+        let mut out = Vec::new();
+        write_stream(&stream, Some(5), &mut out).unwrap();
+        assert_eq!(b"hello".to_vec(), out);
+
+        let stream = BodyStream::new(Cursor::new(b"short".to_vec()));
+        assert!(write_stream(&stream, Some(10), &mut Vec::new()).unwrap_err().is::<BodyError>());
+    }
+}
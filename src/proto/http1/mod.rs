@@ -1,6 +1,8 @@
 use crate::header::HeaderValue;
 
 pub(crate) mod conn;
+pub(crate) mod decode;
+pub(crate) mod encode;
 pub(crate) mod parse;
 
 
@@ -32,7 +34,7 @@ mod parser_test {
     use crate::error::Result;
     use crate::header::{AGE, CONNECTION, CONTENT_LENGTH, CONTENT_TYPE, DATE, SERVER};
     use crate::method::Method;
-    use crate::proto::{HttpParser, ParserResult, RequestParser, ResponseParser};
+    use crate::proto::{Decoder, HttpParser, ParserResult, RequestParser, ResponseParser};
     use crate::request::Request;
     use crate::status::StatusCode;
 
@@ -66,7 +68,7 @@ mod parser_test {
         let resp = r#"HTTP/1.1 404 Not Found
 Date: Fri, 29 May 2020 05:42:19 GMT
 Content-Type: text/html
-Content-Length: 150
+Content-Length: 142
 Connection: keep-alive
 Server: Tengine/2.1.1
 Age: 1
@@ -84,14 +86,14 @@ Access-Control-Allow-Origin: *
 </body>
 </html>"#;
         let mut buf = BytesMut::from(resp);
-        let mut parser = ResponseParser::new();
+        let mut parser = ResponseParser::for_method(&Method::GET);
         let response = parser.parse(&mut buf)?;
         if let ParserResult::Complete(data) = response {
             assert_eq!(StatusCode::from_u16(404)?, data.status());
             let headers = data.headers();
             assert_eq!("Fri, 29 May 2020 05:42:19 GMT", headers.get(DATE).unwrap());
             assert_eq!("text/html", headers.get(CONTENT_TYPE).unwrap());
-            assert_eq!("150", headers.get(CONTENT_LENGTH).unwrap());
+            assert_eq!("142", headers.get(CONTENT_LENGTH).unwrap());
             assert_eq!("keep-alive", headers.get(CONNECTION).unwrap());
             assert_eq!("Tengine/2.1.1", headers.get(SERVER).unwrap());
             assert_eq!("1", headers.get(AGE).unwrap());
@@ -101,18 +103,113 @@ Access-Control-Allow-Origin: *
             assert_eq!("WS", headers.get("X-Cache-Webcdn").unwrap());
             assert_eq!("*", headers.get("Access-Control-Allow-Origin").unwrap());
             let body = data.body();
-            if let BodyKind::Text(text) = body.kind() {
-                assert_eq!(r#"<html>
+            if let BodyKind::Binary(text) = body.kind() {
+                assert_eq!(&br#"<html>
 <head><title>404 Not Found</title></head>
 <body>
 <center><h1>404 Not Found</h1></center>
 <hr><center>openresty</center>
 </body>
-</html>"#, text);
+</html>"#[..], text.as_ref());
+            } else {
+                panic!("body should be binary")
             }
         } else {
             panic!("parse error")
         }
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_chunked_decoder_split_input() -> Result<()> {
+        let data = b"4\r\nwiki\r\n5;ext\r\npedia\r\nE\r\n in\r\n\r\nchunks.\r\n0\r\n\r\nHTTP/1.1";
+        // feed byte by byte
+        let mut decoder = Decoder::chunked();
+        let mut out = BytesMut::new();
+        let mut buf = BytesMut::new();
+        let mut complete = false;
+        for byte in data.iter() {
+            buf.extend_from_slice(&[*byte]);
+            if decoder.decode(&mut buf, &mut out)? {
+                complete = true;
+                break;
+            }
+        }
+        assert!(complete);
+        assert_eq!(&b"wikipedia in\r\n\r\nchunks."[..], out.as_ref());
+
+        // the bytes after body are kept
+        let mut decoder = Decoder::chunked();
+        let mut out = BytesMut::new();
+        let mut buf = BytesMut::from(&data[..]);
+        assert!(decoder.decode(&mut buf, &mut out)?);
+        assert_eq!(&b"HTTP/1.1"[..], buf.as_ref());
+        Ok(())
+    }
+
+    #[test]
+    fn test_length_decoder() -> Result<()> {
+        let mut decoder = Decoder::length(5);
+        let mut out = BytesMut::new();
+        assert!(!decoder.decode(&mut BytesMut::from("hel"), &mut out)?);
+        assert!(decoder.decode_eof().is_err());
+        let mut buf = BytesMut::from("lo world");
+        assert!(decoder.decode(&mut buf, &mut out)?);
+        assert_eq!(&b"hello"[..], out.as_ref());
+        assert_eq!(&b" world"[..], buf.as_ref());
+
+        assert!(Decoder::chunked().decode(&mut BytesMut::from("zz\r\n"), &mut out).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_response_fed_in_chunks() -> Result<()> {
+        let data = b"HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\nhello world\
+            HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n";
+        let mut parser = ResponseParser::for_method(&Method::GET);
+        let mut buf = BytesMut::new();
+        let mut responses = Vec::new();
+        for chunk in data.chunks(7) {
+            buf.extend_from_slice(chunk);
+            while let ParserResult::Complete(resp) = parser.parse(&mut buf)? {
+                assert!(parser.keep_alive());
+                responses.push(resp);
+            }
+        }
+        assert_eq!(2, responses.len());
+        let bodies: Vec<&[u8]> = responses.iter().map(|resp| match resp.body().kind() {
+            BodyKind::Binary(body) => body.as_ref(),
+            _ => panic!("body should be binary"),
+        }).collect();
+        assert_eq!(vec![&b"hello world"[..], &b"abc"[..]], bodies);
+        assert!(buf.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_response_until_eof() -> Result<()> {
+        let mut parser = ResponseParser::for_method(&Method::GET);
+        let mut buf = BytesMut::from("HTTP/1.0 200 OK\r\nServer: test\r\n\r\nfirst");
+        assert!(matches!(parser.parse(&mut buf)?, ParserResult::Partial));
+        buf.extend_from_slice(b" second");
+        assert!(matches!(parser.parse(&mut buf)?, ParserResult::Partial));
+        let resp = parser.parse_eof()?;
+        assert!(!parser.keep_alive());
+        match resp.body().kind() {
+            BodyKind::Binary(body) => assert_eq!(&b"first second"[..], body.as_ref()),
+            _ => panic!("body should be binary"),
+        }
+
+        // connection closed before the whole body received
+        let mut parser = ResponseParser::for_method(&Method::GET);
+        let mut buf = BytesMut::from("HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort");
+        assert!(matches!(parser.parse(&mut buf)?, ParserResult::Partial));
+        assert!(parser.parse_eof().is_err());
+
+        // HEAD response never has body
+        let mut parser = ResponseParser::for_method(&Method::HEAD);
+        let mut buf = BytesMut::from("HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n");
+        assert!(matches!(parser.parse(&mut buf)?, ParserResult::Complete(_)));
+        Ok(())
+    }
+}
@@ -1,15 +1,17 @@
 use std::fmt::Write;
+use std::io::ErrorKind;
 use std::net::SocketAddr;
 
 use bytes::{BufMut, BytesMut};
 use url::Url;
 
-use crate::body::{Body, BodyKind};
+use crate::body::{Body, BodyKind, BodyStream};
 use crate::body_kind;
-use crate::error::{InvalidUrl, Result};
+use crate::error::{BodyError, InvalidHttpVersion, InvalidUrl, IoError, Result};
 use crate::error::Error;
-use crate::header::{CONNECTION, HeaderMap, HeaderName, HeaderValue, InvalidHeaderName};
-use crate::proto::{HttpParser, ParserResult};
+use crate::header::{CONNECTION, CONTENT_LENGTH, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue, InvalidHeaderName, TRAILER, TRANSFER_ENCODING};
+use crate::method::Method;
+use crate::proto::{Decoder, HttpParser, ParserResult};
 use crate::proto::http1::{connection_close, connection_keep_alive};
 use crate::request::Request;
 use crate::response::Response;
@@ -18,20 +20,71 @@ use crate::version::Version;
 
 const MAX_HEADERS: usize = 100;
 
+/// incremental http response parser,
+/// fed with the bytes received from connection until a whole response is parsed
 pub struct ResponseParser {
     keep_alive: bool,
+    method: Method,
+    state: State,
+}
+
+/// the progress of `ResponseParser`
+enum State {
+    /// waiting for the start line and headers
+    Head,
+    /// head parsed, decoding body
+    Body(Response<()>, Decoder, BytesMut),
 }
 
 impl ResponseParser {
-    pub fn new() -> Self {
-        Self { keep_alive: false }
+    /// parse the response of the request use `method`, `HEAD` responses never have body
+    pub fn for_method(method: &Method) -> Self {
+        Self {
+            keep_alive: false,
+            method: method.clone(),
+            state: State::Head,
+        }
     }
-}
 
-impl HttpParser for ResponseParser {
-    type To = Response<Body>;
+    /// is the connection can be reused for next request
+    pub fn keep_alive(&self) -> bool {
+        self.keep_alive
+    }
 
-    fn parse(&mut self, buf: &mut BytesMut) -> Result<ParserResult<Self::To>> {
+    /// the connection was closed, complete the response which is delimited by closing connection
+    pub fn parse_eof(&mut self) -> Result<Response<Body>> {
+        match std::mem::replace(&mut self.state, State::Head) {
+            State::Body(head, mut decoder, body) => {
+                decoder.decode_eof()?;
+                self.keep_alive = false;
+                Ok(head.map(|_| Body::new(BodyKind::Binary(body))))
+            }
+            State::Head => Err(Error::from(IoError::from_kind(ErrorKind::UnexpectedEof))),
+        }
+    }
+
+    /// parse the final response head and return the decoder of its body,
+    /// the informational responses are skipped, the body is left in `buf`
+    pub(crate) fn parse_head_only(&mut self, buf: &mut BytesMut) -> Result<ParserResult<(Response<()>, Decoder)>> {
+        loop {
+            let head = match self.parse_head(buf)? {
+                ParserResult::Complete(head) => head,
+                ParserResult::Partial => return Ok(ParserResult::Partial),
+            };
+            // skip `100 Continue` and other informational responses
+            if head.status().is_informational() && head.status() != StatusCode::SWITCHING_PROTOCOLS {
+                continue;
+            }
+            let decoder = Decoder::for_response(&self.method, head.status(), head.headers())?;
+            if decoder.is_eof() {
+                self.keep_alive = false;
+            }
+            return Ok(ParserResult::Complete((head, decoder)));
+        }
+    }
+
+    /// parse the response start line and headers, the parsed bytes will be removed from `buf`
+    fn parse_head(&mut self, buf: &mut BytesMut) -> Result<ParserResult<Response<()>>> {
         let mut headers_indices = [HeaderIndices::default(); MAX_HEADERS];
 
         let (len, status_code, version, header_len) = {
@@ -48,7 +101,7 @@ impl HttpParser for ResponseParser {
                     };
                     let header_len = resp.headers.len();
 
-                    record_header_indices(buf.as_ref(), &mut header, &mut headers_indices)?;
+                    record_header_indices(buf.as_ref(), resp.headers, &mut headers_indices)?;
                     (len, status_code, version, header_len)
                 }
                 httparse::Status::Partial => {
@@ -80,16 +133,43 @@ impl HttpParser for ResponseParser {
         }
         self.keep_alive = keep_alive;
 
-        let body = BytesMut::from(&buf[header_len..]);
         let parsed_rep = Response::builder()
             .version(version)
             .status(status_code)
             .set_header_map(header_map)
-            .body(Body::new(BodyKind::Binary(body)))?;
+            .body(())?;
 
         Ok(ParserResult::Complete(parsed_rep))
     }
 
+}
+
+impl HttpParser for ResponseParser {
+    type To = Response<Body>;
+
+    fn parse(&mut self, buf: &mut BytesMut) -> Result<ParserResult<Self::To>> {
+        loop {
+            match self.state {
+                State::Head => {
+                    let (head, decoder) = match self.parse_head_only(buf)? {
+                        ParserResult::Complete(parsed) => parsed,
+                        ParserResult::Partial => return Ok(ParserResult::Partial),
+                    };
+                    self.state = State::Body(head, decoder, BytesMut::new());
+                }
+                State::Body(_, ref mut decoder, ref mut body) => {
+                    if !decoder.decode(buf, body)? {
+                        return Ok(ParserResult::Partial);
+                    }
+                    // ready for next response on the same connection
+                    if let State::Body(head, _, body) = std::mem::replace(&mut self.state, State::Head) {
+                        return Ok(ParserResult::Complete(head.map(|_| Body::new(BodyKind::Binary(body)))));
+                    }
+                }
+            }
+        }
+    }
+
     fn encode(_from: Self::To) -> Result<BytesMut> {
         unreachable!("response don't need encode for http client");
     }
@@ -112,11 +192,23 @@ impl RequestParser {
         Ok(addr)
     }
 
+    /// encode request with absolute-form request target, used for forward proxy
+    pub fn encode_absolute(req: Request<Body>) -> Result<BytesMut> {
+        Self::ready(&req, true)
+    }
+
     /// helper function for `new` `with_timeout`
-    fn ready(req: &Request<Body>) -> Result<BytesMut> {
+    fn ready(req: &Request<Body>, absolute: bool) -> Result<BytesMut> {
         let url = req.uri().ok_or(Error::from(InvalidUrl::new("missing url")))?.clone();
+        if req.version() >= Version::HTTP_2 {
+            return Err(Error::from(InvalidHttpVersion::new(&format!("{} request can't be encoded as HTTP/1", req.version().as_str()))));
+        }
         let mut buf = BytesMut::new();
-        Self::ready_start_line(&mut buf, req, &url);
+        if absolute {
+            Self::ready_absolute_start_line(&mut buf, req, &url);
+        } else {
+            Self::ready_start_line(&mut buf, req, &url);
+        }
         Self::ready_headers(&mut buf, req)?;
         Self::end_of_headers(&mut buf);
         Self::ready_body(&mut buf, req)?;
@@ -138,6 +230,15 @@ impl RequestParser {
         }
     }
 
+    /// ready for request start line with absolute url, the userinfo and fragment are removed
+    fn ready_absolute_start_line(buf: &mut BytesMut, req: &Request<Body>, url: &Url) {
+        let mut target = url.clone();
+        let _ = target.set_username("");
+        let _ = target.set_password(None);
+        target.set_fragment(None);
+        buf.write_fmt(format_args!("{} {} {}\r\n", req.method().as_str(), target.as_str(), req.version().as_str())).unwrap();
+    }
+
 
     fn ready_body(buf: &mut BytesMut, req: &Request<Body>) -> Result<()> {
         body_kind!(req.body().kind(),
@@ -149,6 +250,9 @@ impl RequestParser {
             },
             empty => {
 
+            },
+            _stream => {
+                // written to the connection after the head
             }
         );
         Ok(())
@@ -159,6 +263,38 @@ impl RequestParser {
         for (name, value) in req.headers() {
             buf.write_fmt(format_args!("{}: {}\r\n", name.as_str(), value.to_str()?)).expect("failed write data to buffer");
         }
+        if let Some(content_type) = req.body().content_type() {
+            if !req.headers().contains_key(CONTENT_TYPE) {
+                buf.write_fmt(format_args!("content-type: {}\r\n", content_type.to_str()?)).expect("failed write data to buffer");
+            }
+        }
+        if let BodyKind::Stream(stream) = req.body().kind() {
+            Self::ready_stream_headers(buf, req, stream)?;
+        }
+        Ok(())
+    }
+
+    /// the stream with known length is sent with `Content-Length`,
+    /// otherwise it's sent with `Transfer-Encoding: chunked`
+    fn ready_stream_headers(buf: &mut BytesMut, req: &Request<Body>, stream: &BodyStream) -> Result<()> {
+        let headers = req.headers();
+        if headers.contains_key(CONTENT_LENGTH) || headers.contains_key(TRANSFER_ENCODING) {
+            return Ok(());
+        }
+        if let Some(len) = stream.length() {
+            buf.write_fmt(format_args!("content-length: {}\r\n", len)).expect("failed write data to buffer");
+            return Ok(());
+        }
+        if req.version() < Version::HTTP_11 {
+            return Err(Error::from(BodyError::new("chunked body requires HTTP/1.1, set Content-Length for HTTP/1.0")));
+        }
+        buf.write_str("transfer-encoding: chunked\r\n").expect("failed write data to buffer");
+        if let Some(trailers) = stream.trailers() {
+            if !trailers.is_empty() && !headers.contains_key(TRAILER) {
+                let names: Vec<&str> = trailers.keys().map(|name| name.as_str()).collect();
+                buf.write_fmt(format_args!("trailer: {}\r\n", names.join(", "))).expect("failed write data to buffer");
+            }
+        }
         Ok(())
     }
 }
@@ -171,7 +307,7 @@ impl HttpParser for RequestParser {
     }
 
     fn encode(from: Self::To) -> Result<BytesMut> {
-        let req = Self::ready(&from)?;
+        let req = Self::ready(&from, false)?;
 
         Ok(req)
     }
@@ -218,3 +354,28 @@ fn record_header_indices(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use crate::method::Method;
+    use crate::proto::ParserResult;
+    use crate::status::StatusCode;
+
+    use super::ResponseParser;
+
+    #[test]
+    fn test_parse_head() {
+        // only the headers parsed are recorded, not the unused slots of the header array
+        let mut buf = BytesMut::from(&b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nX-Id: 7\r\n\r\nok"[..]);
+        let resp = match ResponseParser::for_method(&Method::GET).parse_head(&mut buf).unwrap() {
+            ParserResult::Complete(resp) => resp,
+            ParserResult::Partial => panic!("the head is complete"),
+        };
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!(2, resp.headers().len());
+        assert_eq!("7", resp.headers()["x-id"]);
+        assert_eq!(&b"ok"[..], &buf[..]);
+    }
+}
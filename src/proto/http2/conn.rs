@@ -0,0 +1,906 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{Read, Write};
+
+use bytes::BytesMut;
+
+use crate::body::{Body, BodyKind};
+use crate::error::{BodyError, Error, Http2Error, InvalidUrl, Result};
+use crate::header::{CONTENT_LENGTH, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue, TE};
+use crate::method::Method;
+use crate::request::Request;
+use crate::response::Response;
+use crate::status::StatusCode;
+use crate::version::Version;
+
+use super::frame::*;
+use super::hpack::{self, DEFAULT_TABLE_SIZE, Decoder};
+
+/// the connection preface sent by client
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+/// the window size of connection and streams advertised to server
+const WINDOW_SIZE: u32 = 1 << 20;
+const READ_BUFFER_SIZE: usize = 16_384;
+
+/// the headers only meaningful for HTTP/1 connection, they are not sent in HTTP/2
+fn is_connection_header(name: &HeaderName) -> bool {
+    matches!(name.as_str(), "connection" | "keep-alive" | "proxy-connection" | "transfer-encoding" | "upgrade" | "host")
+}
+
+/// the request converted to HTTP/2 header block, it can be sent again if the connection is stale
+#[derive(Debug, Clone)]
+pub(crate) struct PreparedRequest {
+    block: Vec<u8>,
+    body: Body,
+}
+
+impl PreparedRequest {
+    /// encode the pseudo headers and headers of `req`,
+    /// the connection specific headers are removed
+    pub fn new(req: Request<Body>) -> Result<Self> {
+        let url = req.uri().ok_or(Error::from(InvalidUrl::new("missing url")))?;
+        if *req.method() == Method::CONNECT {
+            return Err(Error::from(Http2Error::new("CONNECT is not supported by HTTP/2 client")));
+        }
+        let host = url.host_str().ok_or(Error::from(InvalidUrl::new("missing host")))?;
+        let authority = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_owned(),
+        };
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_owned(),
+        };
+        let mut block = Vec::new();
+        hpack::encode(b":method", req.method().as_str().as_bytes(), &mut block);
+        hpack::encode(b":scheme", url.scheme().as_bytes(), &mut block);
+        hpack::encode(b":authority", authority.as_bytes(), &mut block);
+        hpack::encode(b":path", path.as_bytes(), &mut block);
+        for (name, value) in req.headers().iter() {
+            if is_connection_header(name) || (*name == TE && value != "trailers") {
+                continue;
+            }
+            hpack::encode(name.as_str().as_bytes(), value.as_bytes(), &mut block);
+        }
+        if !req.headers().contains_key(CONTENT_TYPE) {
+            if let Some(content_type) = req.body().content_type() {
+                hpack::encode(CONTENT_TYPE.as_str().as_bytes(), content_type.as_bytes(), &mut block);
+            }
+        }
+        if !req.headers().contains_key(CONTENT_LENGTH) {
+            match (req.body().kind(), req.body().content_length()) {
+                (BodyKind::Empty, _) | (_, None) => {}
+                (_, Some(length)) => hpack::encode(CONTENT_LENGTH.as_str().as_bytes(), length.to_string().as_bytes(), &mut block),
+            }
+        }
+        let body = req.into_body();
+        Ok(Self { block, body })
+    }
+
+    /// the body can be sent again, the stream body is taken when the request is sent
+    pub fn is_replayable(&self) -> bool {
+        match self.body.kind() {
+            BodyKind::Stream(stream) => stream.is_replayable(),
+            _ => true,
+        }
+    }
+}
+
+/// the state of a stream
+struct StreamState {
+    send_window: i64,
+    /// the received bytes which have not been released by `WINDOW_UPDATE`
+    recv_unacked: u32,
+    body: Option<Box<dyn Read + Send>>,
+    /// the data read from body but not sent
+    pending: Vec<u8>,
+    trailers: Option<HeaderMap>,
+    /// `END_STREAM` has been sent
+    sent: bool,
+    status: Option<StatusCode>,
+    headers: HeaderMap,
+    data: Vec<u8>,
+    /// `END_STREAM` has been received
+    received: bool,
+    error: Option<Error>,
+}
+
+impl StreamState {
+    fn finished(&self) -> bool {
+        self.received || self.error.is_some()
+    }
+}
+
+/// the part of request body to send
+enum Chunk {
+    /// the data and whether it's the end of stream
+    Data(Vec<u8>, bool),
+    Trailers(HeaderMap),
+    /// failed to read the body, the stream is reset
+    Cancel,
+    /// the flow control window is exhausted
+    Blocked,
+}
+
+/// a client HTTP/2 connection over cleartext with prior knowledge,
+/// the requests are multiplexed on the connection
+pub(crate) struct Connection<S> {
+    stream: S,
+    buf: BytesMut,
+    decoder: Decoder,
+    /// the settings of server
+    max_frame_size: u32,
+    max_concurrent_streams: u32,
+    initial_window_size: u32,
+    send_window: i64,
+    recv_unacked: u32,
+    next_stream_id: u32,
+    streams: HashMap<u32, StreamState>,
+    /// the stream id and header block being received by `CONTINUATION`, and whether the stream ends
+    continuation: Option<(u32, Vec<u8>, bool)>,
+    /// the last stream id will be processed by server after `GOAWAY` received
+    go_away: Option<u32>,
+    broken: bool,
+    /// any response frame received in current batch
+    responded: bool,
+}
+
+impl<S: Read + Write> Connection<S> {
+    /// send the connection preface and settings on opened connection
+    pub fn handshake(mut stream: S) -> Result<Self> {
+        let mut out = PREFACE.to_vec();
+        Frame::settings(&[(SETTINGS_ENABLE_PUSH, 0), (SETTINGS_INITIAL_WINDOW_SIZE, WINDOW_SIZE)]).encode(&mut out);
+        Frame::window_update(0, WINDOW_SIZE - DEFAULT_WINDOW_SIZE).encode(&mut out);
+        stream.write_all(&out)?;
+        stream.flush()?;
+        Ok(Self {
+            stream,
+            buf: BytesMut::with_capacity(READ_BUFFER_SIZE),
+            decoder: Decoder::new(DEFAULT_TABLE_SIZE),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            max_concurrent_streams: u32::MAX,
+            initial_window_size: DEFAULT_WINDOW_SIZE,
+            send_window: i64::from(DEFAULT_WINDOW_SIZE),
+            recv_unacked: 0,
+            next_stream_id: 1,
+            streams: HashMap::new(),
+            continuation: None,
+            go_away: None,
+            broken: false,
+            responded: false,
+        })
+    }
+
+    /// the connection can be used by next requests,
+    /// it can't if the server allows no concurrent stream
+    pub fn is_usable(&self) -> bool {
+        !self.broken && self.go_away.is_none() && self.max_concurrent_streams > 0 && self.next_stream_id < MAX_STREAM_ID
+    }
+
+    /// any response frame was received in last `send_requests`,
+    /// if not, the requests can be sent again on new connection
+    pub fn responded(&self) -> bool {
+        self.responded
+    }
+
+    /// send the requests concurrently, the number of open streams is limited by server,
+    /// the responses are returned in the order of requests.
+    ///
+    /// the connection error fails all requests, the stream error fails the request only
+    pub fn send_requests(&mut self, reqs: &[PreparedRequest]) -> Result<Vec<Result<Response<Body>>>> {
+        self.responded = false;
+        let mut queue: VecDeque<usize> = (0..reqs.len()).collect();
+        let mut results: Vec<Option<Result<Response<Body>>>> = reqs.iter().map(|_| None).collect();
+        let mut ids: HashMap<u32, usize> = HashMap::new();
+        loop {
+            while self.is_usable() && (self.streams.len() as u32) < self.max_concurrent_streams {
+                let index = match queue.pop_front() {
+                    Some(index) => index,
+                    None => break,
+                };
+                match self.open_stream(&reqs[index])? {
+                    Ok(id) => {
+                        ids.insert(id, index);
+                    }
+                    Err(e) => results[index] = Some(Err(e)),
+                }
+            }
+            if !self.is_usable() {
+                let msg = if self.max_concurrent_streams == 0 {
+                    "the server allows no concurrent stream, the request is not sent"
+                } else {
+                    "the connection is closing, the request is not sent"
+                };
+                for index in queue.drain(..) {
+                    results[index] = Some(Err(Error::from(Http2Error::new(msg))));
+                }
+            }
+            self.send_pending()?;
+            let finished: Vec<u32> = self.streams.iter()
+                .filter(|(_, stream)| stream.finished())
+                .map(|(id, _)| *id)
+                .collect();
+            for id in finished {
+                let resp = self.finish_stream(id)?;
+                if let Some(index) = ids.remove(&id) {
+                    results[index] = Some(resp);
+                }
+            }
+            if results.iter().all(Option::is_some) {
+                return Ok(results.into_iter().map(Option::unwrap).collect());
+            }
+            let frame = self.read_frame()?;
+            self.handle_frame(frame)?;
+        }
+    }
+
+    /// send the request head, the body is sent by `send_pending`.
+    /// the outer error is connection error, the inner error is the error of the request
+    fn open_stream(&mut self, req: &PreparedRequest) -> Result<Result<u32>> {
+        let (body, pending, trailers) = match req.body.kind() {
+            BodyKind::Empty => (None, Vec::new(), None),
+            BodyKind::Stream(stream) => match stream.take() {
+                Some(reader) => (Some(reader), Vec::new(), stream.trailers().cloned()),
+                None => return Ok(Err(Error::from(BodyError::new("the body stream has been sent, it can't be sent again")))),
+            },
+            _ => (None, req.body.to_vec()?, None),
+        };
+        let end_stream = body.is_none() && pending.is_empty();
+        let id = self.next_stream_id;
+        self.next_stream_id += 2;
+        self.write_headers(id, &req.block, end_stream)?;
+        self.streams.insert(id, StreamState {
+            send_window: i64::from(self.initial_window_size),
+            recv_unacked: 0,
+            body,
+            pending,
+            trailers,
+            sent: end_stream,
+            status: None,
+            headers: HeaderMap::new(),
+            data: Vec::new(),
+            received: false,
+            error: None,
+        });
+        Ok(Ok(id))
+    }
+
+    /// write the header block in `HEADERS` and `CONTINUATION` frames
+    fn write_headers(&mut self, id: u32, block: &[u8], end_stream: bool) -> Result<()> {
+        let mut out = Vec::with_capacity(block.len() + HEADER_LEN);
+        let mut chunks = block.chunks(self.max_frame_size as usize).peekable();
+        let mut kind = FrameType::Headers;
+        let mut flags = if end_stream { END_STREAM } else { 0 };
+        if chunks.peek().is_none() {
+            Frame::new(kind, flags | END_HEADERS, id, Vec::new()).encode(&mut out);
+        }
+        while let Some(chunk) = chunks.next() {
+            if chunks.peek().is_none() {
+                flags |= END_HEADERS;
+            }
+            Frame::new(kind, flags, id, chunk.to_vec()).encode(&mut out);
+            kind = FrameType::Continuation;
+            flags = 0;
+        }
+        self.write(&out)
+    }
+
+    /// send the request bodies as the flow control windows allow
+    fn send_pending(&mut self) -> Result<()> {
+        let mut ids: Vec<u32> = self.streams.iter()
+            .filter(|(_, stream)| !stream.sent && !stream.finished())
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort();
+        for id in ids {
+            loop {
+                match self.next_chunk(id) {
+                    Chunk::Data(data, end_stream) => {
+                        let flags = if end_stream { END_STREAM } else { 0 };
+                        self.write_frame(&Frame::new(FrameType::Data, flags, id, data))?;
+                        if end_stream {
+                            break;
+                        }
+                    }
+                    Chunk::Trailers(trailers) => {
+                        let mut block = Vec::new();
+                        for (name, value) in trailers.iter() {
+                            hpack::encode(name.as_str().as_bytes(), value.as_bytes(), &mut block);
+                        }
+                        self.write_headers(id, &block, true)?;
+                        break;
+                    }
+                    Chunk::Cancel => {
+                        self.write_frame(&Frame::rst_stream(id, CANCEL))?;
+                        break;
+                    }
+                    Chunk::Blocked => break,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// the next part of request body can be sent on stream `id`
+    fn next_chunk(&mut self, id: u32) -> Chunk {
+        let max_frame_size = self.max_frame_size as usize;
+        let stream = self.streams.get_mut(&id).expect("the stream is open");
+        if stream.pending.is_empty() {
+            if let Some(body) = stream.body.as_mut() {
+                stream.pending.resize(max_frame_size, 0);
+                match body.read(&mut stream.pending) {
+                    Ok(n) => {
+                        stream.pending.truncate(n);
+                        if n == 0 {
+                            stream.body = None;
+                        }
+                    }
+                    Err(e) => {
+                        stream.pending.clear();
+                        stream.error = Some(Error::from(e));
+                        return Chunk::Cancel;
+                    }
+                }
+            }
+        }
+        let last = stream.body.is_none() && stream.trailers.is_none();
+        if stream.pending.is_empty() && stream.body.is_none() {
+            stream.sent = true;
+            return match stream.trailers.take() {
+                Some(trailers) => Chunk::Trailers(trailers),
+                None => Chunk::Data(Vec::new(), true),
+            };
+        }
+        let window = std::cmp::min(self.send_window, stream.send_window);
+        if window <= 0 {
+            return Chunk::Blocked;
+        }
+        let n = std::cmp::min(std::cmp::min(stream.pending.len(), max_frame_size) as i64, window) as usize;
+        let data: Vec<u8> = stream.pending.drain(..n).collect();
+        let end_stream = last && stream.pending.is_empty();
+        if end_stream {
+            stream.sent = true;
+        }
+        stream.send_window -= n as i64;
+        self.send_window -= n as i64;
+        Chunk::Data(data, end_stream)
+    }
+
+    /// remove the finished stream, the request body is cancelled if it's not sent completely
+    fn finish_stream(&mut self, id: u32) -> Result<Result<Response<Body>>> {
+        let stream = self.streams.remove(&id).expect("the stream is finished");
+        if !stream.sent && stream.error.is_none() {
+            self.write_frame(&Frame::rst_stream(id, CANCEL))?;
+        }
+        if let Some(e) = stream.error {
+            return Ok(Err(e));
+        }
+        let status = match stream.status {
+            Some(status) => status,
+            None => return Ok(Err(Error::from(Http2Error::new("the stream ended without response headers")))),
+        };
+        let mut builder = Response::builder()
+            .status(status)
+            .version(Version::HTTP_2);
+        if let Some(headers) = builder.headers_mut() {
+            *headers = stream.headers;
+        }
+        Ok(builder.body(Body::from_vec(stream.data)))
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        if let Err(e) = self.stream.write_all(data).and_then(|_| self.stream.flush()) {
+            self.broken = true;
+            return Err(Error::from(e));
+        }
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        let mut out = Vec::with_capacity(HEADER_LEN + frame.payload.len());
+        frame.encode(&mut out);
+        self.write(&out)
+    }
+
+    /// close the connection with `GOAWAY` because of connection error
+    fn abort(&mut self, code: u32, msg: &str) -> Error {
+        self.broken = true;
+        let last = self.next_stream_id.saturating_sub(2);
+        let _ = self.write_frame(&Frame::go_away(last, code));
+        connection_error(code, msg)
+    }
+
+    /// read a whole frame from connection
+    fn read_frame(&mut self) -> Result<Frame> {
+        loop {
+            match Frame::parse(&mut self.buf, DEFAULT_MAX_FRAME_SIZE) {
+                Ok(Some(frame)) => return Ok(frame),
+                Ok(None) => {}
+                Err(e) => {
+                    self.abort(FRAME_SIZE_ERROR, "frame too large");
+                    return Err(e);
+                }
+            }
+            let mut chunk = [0u8; READ_BUFFER_SIZE];
+            let n = match self.stream.read(&mut chunk) {
+                Ok(n) => n,
+                Err(e) => {
+                    self.broken = true;
+                    return Err(Error::from(e));
+                }
+            };
+            if n == 0 {
+                self.broken = true;
+                return Err(Error::from(Http2Error::new("the connection closed by server")));
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    fn handle_frame(&mut self, frame: Frame) -> Result<()> {
+        if let Some((id, _, _)) = self.continuation {
+            if frame.kind != FrameType::Continuation || frame.stream_id != id {
+                return Err(self.abort(PROTOCOL_ERROR, "expected CONTINUATION frame"));
+            }
+        }
+        match frame.kind {
+            FrameType::Data => self.on_data(frame),
+            FrameType::Headers => {
+                if frame.stream_id == 0 {
+                    return Err(self.abort(PROTOCOL_ERROR, "HEADERS frame on stream 0"));
+                }
+                let block = match frame.data() {
+                    Ok(block) => block.to_vec(),
+                    Err(_) => return Err(self.abort(PROTOCOL_ERROR, "invalid HEADERS frame")),
+                };
+                let end_stream = frame.has(END_STREAM);
+                if frame.has(END_HEADERS) {
+                    self.on_header_block(frame.stream_id, &block, end_stream)
+                } else {
+                    self.continuation = Some((frame.stream_id, block, end_stream));
+                    Ok(())
+                }
+            }
+            FrameType::Continuation => {
+                let (id, mut block, end_stream) = match self.continuation.take() {
+                    Some(continuation) => continuation,
+                    None => return Err(self.abort(PROTOCOL_ERROR, "unexpected CONTINUATION frame")),
+                };
+                block.extend_from_slice(&frame.payload);
+                if frame.has(END_HEADERS) {
+                    self.on_header_block(id, &block, end_stream)
+                } else {
+                    self.continuation = Some((id, block, end_stream));
+                    Ok(())
+                }
+            }
+            FrameType::RstStream => {
+                let code = match frame.u32_payload() {
+                    Ok(code) => code,
+                    Err(_) => return Err(self.abort(FRAME_SIZE_ERROR, "invalid RST_STREAM frame")),
+                };
+                self.responded = true;
+                if let Some(stream) = self.streams.get_mut(&frame.stream_id) {
+                    // the server may stop the request body after the whole response sent
+                    if !(stream.received && code == NO_ERROR) {
+                        stream.error = Some(Error::from(Http2Error::new(&format!("stream reset by server: {}", error_name(code)))));
+                    }
+                    stream.sent = true;
+                }
+                Ok(())
+            }
+            FrameType::Settings => self.on_settings(frame),
+            FrameType::Ping => {
+                if frame.payload.len() != 8 {
+                    return Err(self.abort(FRAME_SIZE_ERROR, "invalid PING frame"));
+                }
+                if frame.has(ACK) {
+                    return Ok(());
+                }
+                self.write_frame(&Frame::new(FrameType::Ping, ACK, 0, frame.payload))
+            }
+            FrameType::GoAway => {
+                if frame.payload.len() < 8 {
+                    return Err(self.abort(FRAME_SIZE_ERROR, "invalid GOAWAY frame"));
+                }
+                let last = u32::from_be_bytes([frame.payload[0], frame.payload[1], frame.payload[2], frame.payload[3]]) & MAX_STREAM_ID;
+                let code = u32::from_be_bytes([frame.payload[4], frame.payload[5], frame.payload[6], frame.payload[7]]);
+                debug!("GOAWAY received, last stream {}, {}", last, error_name(code));
+                self.go_away = Some(last);
+                // the server is closing the connection because of error, no stream will be completed
+                if code != NO_ERROR {
+                    self.broken = true;
+                }
+                for (id, stream) in self.streams.iter_mut() {
+                    if *id > last || code != NO_ERROR {
+                        stream.error = Some(Error::from(Http2Error::new(&format!(
+                            "the stream is refused by server, GOAWAY {}", error_name(code)))));
+                    }
+                }
+                Ok(())
+            }
+            FrameType::WindowUpdate => self.on_window_update(frame),
+            FrameType::PushPromise => Err(self.abort(PROTOCOL_ERROR, "PUSH_PROMISE received while push is disabled")),
+            FrameType::Priority | FrameType::Unknown(_) => Ok(()),
+        }
+    }
+
+    fn on_data(&mut self, frame: Frame) -> Result<()> {
+        if frame.stream_id == 0 {
+            return Err(self.abort(PROTOCOL_ERROR, "DATA frame on stream 0"));
+        }
+        self.responded = true;
+        let len = frame.payload.len() as u32;
+        self.recv_unacked += len;
+        if self.recv_unacked >= WINDOW_SIZE / 2 {
+            let increment = std::mem::replace(&mut self.recv_unacked, 0);
+            self.write_frame(&Frame::window_update(0, increment))?;
+        }
+        let data = match frame.data() {
+            Ok(data) => data,
+            Err(_) => return Err(self.abort(PROTOCOL_ERROR, "invalid DATA frame")),
+        };
+        let id = frame.stream_id;
+        let stream = match self.streams.get_mut(&id) {
+            Some(stream) if !stream.finished() => stream,
+            // the data of closed stream is discarded
+            _ => return Ok(()),
+        };
+        if stream.status.is_none() {
+            stream.error = Some(Error::from(Http2Error::new("DATA frame received before response headers")));
+            return self.write_frame(&Frame::rst_stream(id, PROTOCOL_ERROR));
+        }
+        stream.data.extend_from_slice(data);
+        if frame.has(END_STREAM) {
+            stream.received = true;
+            return Ok(());
+        }
+        stream.recv_unacked += len;
+        if stream.recv_unacked >= WINDOW_SIZE / 2 {
+            let increment = std::mem::replace(&mut stream.recv_unacked, 0);
+            self.write_frame(&Frame::window_update(id, increment))?;
+        }
+        Ok(())
+    }
+
+    /// the block is always decoded to keep the dynamic table in sync
+    fn on_header_block(&mut self, id: u32, block: &[u8], end_stream: bool) -> Result<()> {
+        let fields = match self.decoder.decode(block) {
+            Ok(fields) => fields,
+            Err(e) => return Err(self.abort(COMPRESSION_ERROR, &e.to_string())),
+        };
+        self.responded = true;
+        let stream = match self.streams.get_mut(&id) {
+            Some(stream) if !stream.finished() => stream,
+            _ => return Ok(()),
+        };
+        if stream.status.is_some() {
+            // the trailers are not exposed
+            if !end_stream {
+                stream.error = Some(Error::from(Http2Error::new("trailers received without END_STREAM")));
+                return self.write_frame(&Frame::rst_stream(id, PROTOCOL_ERROR));
+            }
+            stream.received = true;
+            return Ok(());
+        }
+        match response_head(fields) {
+            // the informational response is skipped
+            Ok((status, _)) if status.is_informational() => {}
+            Ok((status, headers)) => {
+                stream.status = Some(status);
+                stream.headers = headers;
+            }
+            Err(e) => {
+                stream.error = Some(e);
+                return self.write_frame(&Frame::rst_stream(id, PROTOCOL_ERROR));
+            }
+        }
+        if end_stream {
+            stream.received = true;
+        }
+        Ok(())
+    }
+
+    fn on_settings(&mut self, frame: Frame) -> Result<()> {
+        if frame.stream_id != 0 {
+            return Err(self.abort(PROTOCOL_ERROR, "SETTINGS frame on stream"));
+        }
+        if frame.has(ACK) {
+            return Ok(());
+        }
+        let params = match frame.settings_params() {
+            Ok(params) => params,
+            Err(_) => return Err(self.abort(FRAME_SIZE_ERROR, "invalid SETTINGS frame")),
+        };
+        for (id, value) in params {
+            match id {
+                SETTINGS_MAX_CONCURRENT_STREAMS => self.max_concurrent_streams = value,
+                SETTINGS_INITIAL_WINDOW_SIZE => {
+                    if value > MAX_WINDOW_SIZE {
+                        return Err(self.abort(FLOW_CONTROL_ERROR, "initial window size too large"));
+                    }
+                    let delta = i64::from(value) - i64::from(self.initial_window_size);
+                    for stream in self.streams.values_mut() {
+                        stream.send_window += delta;
+                    }
+                    self.initial_window_size = value;
+                }
+                SETTINGS_MAX_FRAME_SIZE => {
+                    if !(DEFAULT_MAX_FRAME_SIZE..=MAX_FRAME_SIZE).contains(&value) {
+                        return Err(self.abort(PROTOCOL_ERROR, "invalid max frame size"));
+                    }
+                    self.max_frame_size = value;
+                }
+                // the encoder never uses the dynamic table, the other settings don't affect client
+                _ => {}
+            }
+        }
+        self.write_frame(&Frame::new(FrameType::Settings, ACK, 0, Vec::new()))
+    }
+
+    fn on_window_update(&mut self, frame: Frame) -> Result<()> {
+        let increment = match frame.u32_payload() {
+            Ok(increment) => i64::from(increment & MAX_WINDOW_SIZE),
+            Err(_) => return Err(self.abort(FRAME_SIZE_ERROR, "invalid WINDOW_UPDATE frame")),
+        };
+        if frame.stream_id == 0 {
+            self.send_window += increment;
+            if increment == 0 || self.send_window > i64::from(MAX_WINDOW_SIZE) {
+                return Err(self.abort(FLOW_CONTROL_ERROR, "invalid connection window update"));
+            }
+            return Ok(());
+        }
+        let id = frame.stream_id;
+        if let Some(stream) = self.streams.get_mut(&id) {
+            stream.send_window += increment;
+            if increment == 0 || stream.send_window > i64::from(MAX_WINDOW_SIZE) {
+                stream.error = Some(Error::from(Http2Error::new("invalid stream window update")));
+                return self.write_frame(&Frame::rst_stream(id, FLOW_CONTROL_ERROR));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the status and headers of response, the pseudo headers other than `:status` are not allowed
+fn response_head(fields: Vec<(Vec<u8>, Vec<u8>)>) -> Result<(StatusCode, HeaderMap)> {
+    let mut status = None;
+    let mut headers = HeaderMap::with_capacity(fields.len());
+    for (name, value) in fields {
+        if name == b":status" {
+            status = Some(StatusCode::from_bytes(&value)?);
+        } else if name.starts_with(b":") {
+            return Err(Error::from(Http2Error::new(&format!(
+                "invalid pseudo header {} in response", String::from_utf8_lossy(&name)))));
+        } else {
+            headers.append(HeaderName::from_bytes(&name)?, HeaderValue::from_bytes(&value)?);
+        }
+    }
+    match status {
+        Some(status) => Ok((status, headers)),
+        None => Err(Error::from(Http2Error::new("missing :status in response"))),
+    }
+}
+
+impl<S> fmt::Debug for Connection<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("next_stream_id", &self.next_stream_id)
+            .field("streams", &self.streams.len())
+            .field("go_away", &self.go_away)
+            .field("broken", &self.broken)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread::JoinHandle;
+
+    use bytes::BytesMut;
+    use url::Url;
+
+    use crate::body::Body;
+    use crate::client::tests::local_client;
+    use crate::method::Method;
+    use crate::request::Request;
+    use crate::version::Version;
+
+    use super::{Connection, PREFACE, PreparedRequest};
+    use super::super::frame::*;
+    use super::super::hpack::{self, DEFAULT_TABLE_SIZE, Decoder};
+
+    /// the header fields of the requests received by server
+    type Requests = Vec<Vec<(String, String)>>;
+
+    fn read_frame(stream: &mut TcpStream, buf: &mut BytesMut) -> Option<Frame> {
+        loop {
+            if let Some(frame) = Frame::parse(buf, DEFAULT_MAX_FRAME_SIZE).unwrap() {
+                return Some(frame);
+            }
+            let mut chunk = [0u8; 4096];
+            match stream.read(&mut chunk) {
+                Ok(n) if n > 0 => buf.extend_from_slice(&chunk[..n]),
+                _ => return None,
+            }
+        }
+    }
+
+    /// a h2c server answers `count` requests with the path and the request body,
+    /// the responses of the requests received together are sent in reverse order
+    fn serve_h2(count: usize, max_streams: u32) -> (Url, JoinHandle<Requests>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            answer_h2(stream, count, max_streams)
+        });
+        (url, handle)
+    }
+
+    /// answer `count` requests on the accepted connection
+    fn answer_h2(mut stream: TcpStream, count: usize, max_streams: u32) -> Requests {
+        let mut preface = [0u8; 24];
+        stream.read_exact(&mut preface).unwrap();
+        assert_eq!(PREFACE, &preface[..]);
+        let mut out = Vec::new();
+        Frame::settings(&[(SETTINGS_MAX_CONCURRENT_STREAMS, max_streams)]).encode(&mut out);
+        stream.write_all(&out).unwrap();
+
+        let mut buf = BytesMut::new();
+        let mut decoder = Decoder::new(DEFAULT_TABLE_SIZE);
+        let mut requests = Vec::new();
+        let mut batch: Vec<(u32, Vec<u8>)> = Vec::new();
+        let mut bodies: Vec<(u32, Vec<u8>)> = Vec::new();
+        while requests.len() < count || !bodies.is_empty() {
+            let frame = read_frame(&mut stream, &mut buf).expect("request frame");
+            match frame.kind {
+                FrameType::Headers => {
+                    let fields: Vec<(String, String)> = decoder.decode(frame.data().unwrap()).unwrap().into_iter()
+                        .map(|(name, value)| (String::from_utf8(name).unwrap(), String::from_utf8(value).unwrap()))
+                        .collect();
+                    let path = fields.iter().find(|(name, _)| name == ":path").unwrap().1.clone();
+                    requests.push(fields);
+                    bodies.push((frame.stream_id, path.into_bytes()));
+                }
+                FrameType::Data => {
+                    let body = bodies.iter_mut().find(|(id, _)| *id == frame.stream_id).unwrap();
+                    body.1.extend_from_slice(frame.data().unwrap());
+                }
+                _ => {}
+            }
+            let is_request = frame.kind == FrameType::Headers || frame.kind == FrameType::Data;
+            if is_request && frame.has(END_STREAM) {
+                let index = bodies.iter().position(|(id, _)| *id == frame.stream_id).unwrap();
+                batch.push(bodies.remove(index));
+            }
+            if batch.len() as u32 == max_streams || !batch.is_empty() && bodies.is_empty() {
+                let mut out = Vec::new();
+                for (id, body) in batch.drain(..).rev() {
+                    let mut block = Vec::new();
+                    hpack::encode(b":status", b"200", &mut block);
+                    hpack::encode(b"content-type", b"text/plain", &mut block);
+                    Frame::new(FrameType::Headers, END_HEADERS, id, block).encode(&mut out);
+                    Frame::new(FrameType::Data, END_STREAM, id, body).encode(&mut out);
+                }
+                stream.write_all(&out).unwrap();
+            }
+        }
+        requests
+    }
+
+    fn request(url: &Url, method: Method, path: &str, body: Body) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .version(Version::HTTP_2)
+            .header("Host", url.host_str().unwrap())
+            .header("Connection", "keep-alive")
+            .uri(url.join(path).unwrap())
+            .body(body).unwrap()
+    }
+
+    fn text(resp: &crate::response::Response<Body>) -> String {
+        String::from_utf8(resp.body().to_vec().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_prepared_request() {
+        let url = Url::parse("http://example.com:8080/a?b=1").unwrap();
+        let req = PreparedRequest::new(request(&url, Method::POST, "/a?b=1", Body::from_string("data".to_owned()))).unwrap();
+        let fields: Vec<(Vec<u8>, Vec<u8>)> = Decoder::new(DEFAULT_TABLE_SIZE).decode(&req.block).unwrap();
+        let names: Vec<&[u8]> = fields.iter().map(|(name, _)| name.as_slice()).collect();
+        assert_eq!(vec![&b":method"[..], b":scheme", b":authority", b":path", b"content-length"], names);
+        assert_eq!(b"example.com:8080", &fields[2].1[..]);
+        assert_eq!(b"/a?b=1", &fields[3].1[..]);
+        assert_eq!(b"4", &fields[4].1[..]);
+
+        let req = Request::builder().method(Method::CONNECT).uri(url).body(Body::empty()).unwrap();
+        assert!(PreparedRequest::new(req).is_err());
+    }
+
+    #[test]
+    fn test_multiplexed_streams() {
+        let (url, handle) = serve_h2(3, 2);
+        let stream = TcpStream::connect(url.socket_addrs(|| None).unwrap()[0]).unwrap();
+        let mut conn = Connection::handshake(stream).unwrap();
+        let reqs = vec![
+            PreparedRequest::new(request(&url, Method::GET, "/a", Body::empty())).unwrap(),
+            PreparedRequest::new(request(&url, Method::POST, "/b", Body::from_string(":body".to_owned()))).unwrap(),
+            PreparedRequest::new(request(&url, Method::GET, "/c", Body::empty())).unwrap(),
+        ];
+        let resps = conn.send_requests(&reqs).unwrap();
+        let resps: Vec<_> = resps.into_iter().map(Result::unwrap).collect();
+        assert!(resps.iter().all(|resp| resp.version() == Version::HTTP_2));
+        assert_eq!("text/plain", resps[0].headers()["content-type"]);
+        assert_eq!(vec!["/a", "/b:body", "/c"], resps.iter().map(text).collect::<Vec<_>>());
+        assert!(conn.is_usable());
+        assert!(conn.responded());
+
+        let requests = handle.join().unwrap();
+        assert!(requests.iter().all(|fields| fields.iter().all(|(name, _)| name != "connection" && name != "host")));
+    }
+
+    #[test]
+    fn test_client_h2() {
+        let (url, handle) = serve_h2(3, 100);
+        let mut client = local_client();
+        let resp = client.send(request(&url, Method::GET, "/first", Body::empty())).unwrap();
+        assert_eq!(Version::HTTP_2, resp.version());
+        assert_eq!("/first", text(&resp));
+
+        // the pooled connection is reused
+        let resps = client.send_multiplexed(vec![
+            request(&url, Method::GET, "/second", Body::empty()),
+            request(&url, Method::PUT, "/third", Body::from_string("!".to_owned())),
+        ]).unwrap();
+        assert_eq!("/second", text(resps[0].as_ref().unwrap()));
+        assert_eq!("/third!", text(resps[1].as_ref().unwrap()));
+        assert_eq!(3, handle.join().unwrap().len());
+
+        let other = Url::parse("http://127.0.0.1:1/").unwrap();
+        assert!(client.send_multiplexed(vec![
+            request(&url, Method::GET, "/", Body::empty()),
+            request(&other, Method::GET, "/", Body::empty()),
+        ]).is_err());
+    }
+
+    #[test]
+    fn test_client_h2_stream_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let handle = std::thread::spawn(move || {
+            (0..2).map(|_| answer_h2(listener.accept().unwrap().0, 1, 100).len()).sum::<usize>()
+        });
+        let mut client = local_client();
+        assert_eq!("/first", text(&client.send(request(&url, Method::GET, "/first", Body::empty())).unwrap()));
+        // the pooled connection is closed by server, the stream body is only sent on new connection
+        let resp = client.send(request(&url, Method::POST, "/upload", Body::from_reader(&b":data"[..]))).unwrap();
+        assert_eq!("/upload:data", text(&resp));
+        assert_eq!(2, handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_no_concurrent_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            answer_h2(stream.try_clone().unwrap(), 1, 0);
+            // keep the connection open until the client closes it
+            let _ = stream.read_to_end(&mut Vec::new());
+        });
+        let stream = TcpStream::connect(url.socket_addrs(|| None).unwrap()[0]).unwrap();
+        let mut conn = Connection::handshake(stream).unwrap();
+        let req = PreparedRequest::new(request(&url, Method::GET, "/a", Body::empty())).unwrap();
+        let resps = conn.send_requests(std::slice::from_ref(&req)).unwrap();
+        assert_eq!("/a", text(resps[0].as_ref().unwrap()));
+        assert!(!conn.is_usable());
+
+        let resps = conn.send_requests(&[req]).unwrap();
+        assert!(resps[0].is_err());
+        drop(conn);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use bytes::{Buf, BytesMut};
+
+use crate::error::{Error, Http2Error, Result};
+
+/// the length of frame header
+pub(crate) const HEADER_LEN: usize = 9;
+/// the initial max frame size and window size
+pub(crate) const DEFAULT_MAX_FRAME_SIZE: u32 = 16_384;
+pub(crate) const DEFAULT_WINDOW_SIZE: u32 = 65_535;
+/// the max value of window size and max frame size
+pub(crate) const MAX_WINDOW_SIZE: u32 = (1 << 31) - 1;
+pub(crate) const MAX_FRAME_SIZE: u32 = (1 << 24) - 1;
+/// the max value of stream identifier, the reserved bit is excluded
+pub(crate) const MAX_STREAM_ID: u32 = (1 << 31) - 1;
+
+/// the frame flags
+pub(crate) const END_STREAM: u8 = 0x1;
+pub(crate) const ACK: u8 = 0x1;
+pub(crate) const END_HEADERS: u8 = 0x4;
+pub(crate) const PADDED: u8 = 0x8;
+pub(crate) const PRIORITY: u8 = 0x20;
+
+/// the settings identifiers
+pub(crate) const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+pub(crate) const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+pub(crate) const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+pub(crate) const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+/// the error codes used by `RST_STREAM` and `GOAWAY`
+pub(crate) const NO_ERROR: u32 = 0x0;
+pub(crate) const PROTOCOL_ERROR: u32 = 0x1;
+pub(crate) const FLOW_CONTROL_ERROR: u32 = 0x3;
+pub(crate) const FRAME_SIZE_ERROR: u32 = 0x6;
+pub(crate) const CANCEL: u32 = 0x8;
+pub(crate) const COMPRESSION_ERROR: u32 = 0x9;
+
+/// the name of error code
+pub(crate) fn error_name(code: u32) -> &'static str {
+    match code {
+        0x0 => "NO_ERROR",
+        0x1 => "PROTOCOL_ERROR",
+        0x2 => "INTERNAL_ERROR",
+        0x3 => "FLOW_CONTROL_ERROR",
+        0x4 => "SETTINGS_TIMEOUT",
+        0x5 => "STREAM_CLOSED",
+        0x6 => "FRAME_SIZE_ERROR",
+        0x7 => "REFUSED_STREAM",
+        0x8 => "CANCEL",
+        0x9 => "COMPRESSION_ERROR",
+        0xa => "CONNECT_ERROR",
+        0xb => "ENHANCE_YOUR_CALM",
+        0xc => "INADEQUATE_SECURITY",
+        0xd => "HTTP_1_1_REQUIRED",
+        _ => "UNKNOWN_ERROR",
+    }
+}
+
+/// the frame types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FrameType {
+    Data,
+    Headers,
+    Priority,
+    RstStream,
+    Settings,
+    PushPromise,
+    Ping,
+    GoAway,
+    WindowUpdate,
+    Continuation,
+    Unknown(u8),
+}
+
+impl From<u8> for FrameType {
+    fn from(kind: u8) -> Self {
+        match kind {
+            0x0 => FrameType::Data,
+            0x1 => FrameType::Headers,
+            0x2 => FrameType::Priority,
+            0x3 => FrameType::RstStream,
+            0x4 => FrameType::Settings,
+            0x5 => FrameType::PushPromise,
+            0x6 => FrameType::Ping,
+            0x7 => FrameType::GoAway,
+            0x8 => FrameType::WindowUpdate,
+            0x9 => FrameType::Continuation,
+            kind => FrameType::Unknown(kind),
+        }
+    }
+}
+
+impl From<FrameType> for u8 {
+    fn from(kind: FrameType) -> Self {
+        match kind {
+            FrameType::Data => 0x0,
+            FrameType::Headers => 0x1,
+            FrameType::Priority => 0x2,
+            FrameType::RstStream => 0x3,
+            FrameType::Settings => 0x4,
+            FrameType::PushPromise => 0x5,
+            FrameType::Ping => 0x6,
+            FrameType::GoAway => 0x7,
+            FrameType::WindowUpdate => 0x8,
+            FrameType::Continuation => 0x9,
+            FrameType::Unknown(kind) => kind,
+        }
+    }
+}
+
+/// a HTTP/2 frame
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Frame {
+    pub kind: FrameType,
+    pub flags: u8,
+    pub stream_id: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(kind: FrameType, flags: u8, stream_id: u32, payload: Vec<u8>) -> Self {
+        Self { kind, flags, stream_id, payload }
+    }
+
+    /// the `SETTINGS` frame with given parameters
+    pub fn settings(params: &[(u16, u32)]) -> Self {
+        let mut payload = Vec::with_capacity(params.len() * 6);
+        for (id, value) in params {
+            payload.extend_from_slice(&id.to_be_bytes());
+            payload.extend_from_slice(&value.to_be_bytes());
+        }
+        Self::new(FrameType::Settings, 0, 0, payload)
+    }
+
+    pub fn window_update(stream_id: u32, increment: u32) -> Self {
+        Self::new(FrameType::WindowUpdate, 0, stream_id, increment.to_be_bytes().to_vec())
+    }
+
+    pub fn rst_stream(stream_id: u32, code: u32) -> Self {
+        Self::new(FrameType::RstStream, 0, stream_id, code.to_be_bytes().to_vec())
+    }
+
+    pub fn go_away(last_stream_id: u32, code: u32) -> Self {
+        let mut payload = last_stream_id.to_be_bytes().to_vec();
+        payload.extend_from_slice(&code.to_be_bytes());
+        Self::new(FrameType::GoAway, 0, 0, payload)
+    }
+
+    pub fn has(&self, flag: u8) -> bool {
+        self.flags & flag == flag
+    }
+
+    /// write the frame to `dst`
+    pub fn encode(&self, dst: &mut Vec<u8>) {
+        let len = self.payload.len() as u32;
+        dst.extend_from_slice(&len.to_be_bytes()[1..]);
+        dst.push(self.kind.into());
+        dst.push(self.flags);
+        dst.extend_from_slice(&(self.stream_id & MAX_STREAM_ID).to_be_bytes());
+        dst.extend_from_slice(&self.payload);
+    }
+
+    /// parse a frame from `buf`, `None` if more data is needed,
+    /// the frame larger than `max_frame_size` is an error
+    pub fn parse(buf: &mut BytesMut, max_frame_size: u32) -> Result<Option<Frame>> {
+        if buf.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes([0, buf[0], buf[1], buf[2]]);
+        if len > max_frame_size {
+            return Err(connection_error(FRAME_SIZE_ERROR, &format!("frame of {} bytes is too large", len)));
+        }
+        if buf.len() < HEADER_LEN + len as usize {
+            return Ok(None);
+        }
+        let kind = FrameType::from(buf[3]);
+        let flags = buf[4];
+        let stream_id = u32::from_be_bytes([buf[5], buf[6], buf[7], buf[8]]) & MAX_STREAM_ID;
+        buf.advance(HEADER_LEN);
+        let payload = buf.split_to(len as usize).to_vec();
+        Ok(Some(Frame { kind, flags, stream_id, payload }))
+    }
+
+    /// the payload of `DATA` or `HEADERS` without padding,
+    /// the priority fields of `HEADERS` are removed too
+    pub fn data(&self) -> Result<&[u8]> {
+        let mut data = &self.payload[..];
+        let mut padding = 0;
+        if self.has(PADDED) {
+            padding = *data.first().ok_or_else(|| connection_error(PROTOCOL_ERROR, "missing pad length"))? as usize;
+            data = &data[1..];
+        }
+        if self.kind == FrameType::Headers && self.has(PRIORITY) {
+            if data.len() < 5 {
+                return Err(connection_error(FRAME_SIZE_ERROR, "invalid priority fields"));
+            }
+            data = &data[5..];
+        }
+        if padding > data.len() {
+            return Err(connection_error(PROTOCOL_ERROR, "padding exceeds the payload"));
+        }
+        Ok(&data[..data.len() - padding])
+    }
+
+    /// the first 4 bytes of payload as integer, used by `RST_STREAM` and `WINDOW_UPDATE`
+    pub fn u32_payload(&self) -> Result<u32> {
+        if self.payload.len() != 4 {
+            return Err(connection_error(FRAME_SIZE_ERROR, &format!("invalid {:?} frame", self.kind)));
+        }
+        Ok(u32::from_be_bytes([self.payload[0], self.payload[1], self.payload[2], self.payload[3]]))
+    }
+
+    /// the parameters of `SETTINGS` frame
+    pub fn settings_params(&self) -> Result<Vec<(u16, u32)>> {
+        if !self.payload.len().is_multiple_of(6) {
+            return Err(connection_error(FRAME_SIZE_ERROR, "invalid SETTINGS frame"));
+        }
+        Ok(self.payload.chunks(6)
+            .map(|param| (u16::from_be_bytes([param[0], param[1]]), u32::from_be_bytes([param[2], param[3], param[4], param[5]])))
+            .collect())
+    }
+}
+
+/// the error of connection, the code is sent by `GOAWAY`
+pub(crate) fn connection_error(code: u32, msg: &str) -> Error {
+    Error::from(Http2Error::new(&format!("connection error {}: {}", error_name(code), msg)))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use super::{END_HEADERS, Frame, FrameType, PADDED, PRIORITY};
+
+    #[test]
+    fn test_encode_and_parse() {
+        let frame = Frame::new(FrameType::Headers, END_HEADERS, 3, b"block".to_vec());
+        let mut dst = Vec::new();
+        frame.encode(&mut dst);
+        assert_eq!(&[0, 0, 5, 1, 4, 0, 0, 0, 3], &dst[..9]);
+        let mut buf = BytesMut::from(&dst[..4]);
+        assert_eq!(None, Frame::parse(&mut buf, 16_384).unwrap());
+        let mut buf = BytesMut::from(&dst[..]);
+        assert_eq!(Some(frame), Frame::parse(&mut buf, 16_384).unwrap());
+        assert!(buf.is_empty());
+        let mut buf = BytesMut::from(&dst[..]);
+        assert!(Frame::parse(&mut buf, 4).is_err());
+    }
+
+    #[test]
+    fn test_padding_and_priority() {
+        let mut payload = vec![2];
+        payload.extend_from_slice(&[0x80, 0, 0, 1, 15]);
+        payload.extend_from_slice(b"block");
+        payload.extend_from_slice(&[0, 0]);
+        let frame = Frame::new(FrameType::Headers, END_HEADERS | PADDED | PRIORITY, 1, payload);
+        assert_eq!(b"block", frame.data().unwrap());
+
+        let frame = Frame::new(FrameType::Data, PADDED, 1, vec![10, b'a']);
+        assert!(frame.data().is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::VecDeque;
+
+/// the static table, RFC 7541 Appendix A, the index starts from 1
+pub(crate) const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// the size of an entry is the length of name and value plus 32
+const ENTRY_OVERHEAD: usize = 32;
+
+/// the dynamic table used by decoder, the newest entry has the lowest index
+#[derive(Debug)]
+pub(crate) struct DynamicTable {
+    entries: VecDeque<(Vec<u8>, Vec<u8>)>,
+    size: usize,
+    max_size: usize,
+}
+
+impl DynamicTable {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            size: 0,
+            max_size,
+        }
+    }
+
+    /// change the max size, the oldest entries are evicted until the table fits
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.evict(0);
+    }
+
+    /// the entry of index in the combined index space of static and dynamic table
+    pub fn get(&self, index: usize) -> Option<(&[u8], &[u8])> {
+        if index == 0 {
+            return None;
+        }
+        if index <= STATIC_TABLE.len() {
+            let (name, value) = STATIC_TABLE[index - 1];
+            return Some((name.as_bytes(), value.as_bytes()));
+        }
+        self.entries.get(index - STATIC_TABLE.len() - 1)
+            .map(|(name, value)| (name.as_slice(), value.as_slice()))
+    }
+
+    /// insert an entry, the entry larger than the table empties the table
+    pub fn insert(&mut self, name: Vec<u8>, value: Vec<u8>) {
+        let size = name.len() + value.len() + ENTRY_OVERHEAD;
+        self.evict(size);
+        if size <= self.max_size {
+            self.size += size;
+            self.entries.push_front((name, value));
+        }
+    }
+
+    /// evict the oldest entries until there is room for `additional` bytes
+    fn evict(&mut self, additional: usize) {
+        while self.size + additional > self.max_size {
+            match self.entries.pop_back() {
+                Some((name, value)) => self.size -= name.len() + value.len() + ENTRY_OVERHEAD,
+                None => break,
+            }
+        }
+    }
+}
+
+/// the index of the entry in static table which matches the name and value,
+/// and the index of the first entry which matches the name only
+pub(crate) fn find_static(name: &[u8], value: &[u8]) -> (Option<usize>, Option<usize>) {
+    let mut name_index = None;
+    for (index, (entry_name, entry_value)) in STATIC_TABLE.iter().enumerate() {
+        if entry_name.as_bytes() == name {
+            if entry_value.as_bytes() == value {
+                return (Some(index + 1), Some(index + 1));
+            }
+            if name_index.is_none() {
+                name_index = Some(index + 1);
+            }
+        }
+    }
+    (None, name_index)
+}
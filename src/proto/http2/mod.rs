@@ -0,0 +1,5 @@
+pub(crate) use self::conn::{Connection, PreparedRequest};
+
+mod conn;
+mod frame;
+mod hpack;
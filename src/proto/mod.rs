@@ -1,15 +1,27 @@
+use std::any::Any;
 use std::io::{Read, Write};
 use std::net::{SocketAddr, TcpStream};
 
 use bytes::BytesMut;
+use url::Url;
 
 pub use http1::conn::{HttpConfig, HttpConnector};
+pub(crate) use http1::connection_close;
+pub(crate) use http1::decode::Decoder;
+pub(crate) use http1::encode::{stream_length, write_stream};
+pub(crate) use http2::{Connection as Http2Connection, PreparedRequest};
 pub use http1::parse::{RequestParser, ResponseParser};
+pub use socks::Socks5Connector;
+#[cfg(feature = "tls")]
+pub use tls::{MaybeTlsStream, TlsConfig, TlsConnector};
 
-use crate::error::Result;
+use crate::error::{Error, Result, TlsError};
 
 mod http1;
 mod http2;
+mod socks;
+#[cfg(feature = "tls")]
+mod tls;
 
 #[derive(Debug)]
 pub enum ParserResult<T> {
@@ -17,6 +29,18 @@ pub enum ParserResult<T> {
     Partial,
 }
 
+/// An opened connection detached from `Connector` by `take_stream`
+pub trait Stream: Read + Write + Send + 'static {
+    /// convert the connection to `Any`, so it can be downcast to the concrete type in `Connector::set_stream`
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Read + Write + Send + 'static> Stream for T {
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
 /// the HttpClient inner type
 /// usr can implement Connector and use it by HttpClient::from_connector()
 pub trait Connector: Read + Write {
@@ -24,6 +48,33 @@ pub trait Connector: Read + Write {
     fn create_connection(&mut self, socket_addr: &SocketAddr) -> Result<TcpStream>;
     /// connect to socket addr
     fn connect_to(&mut self, addr: &SocketAddr) -> Result<()>;
+
+    /// detach the opened connection from connector, so it can be reused later.
+    ///
+    /// Default is `None`, the connections are not reused,
+    /// and `HttpClient::send_streaming`, HTTP/2 and WebSocket are not supported
+    fn take_stream(&mut self) -> Option<Box<dyn Stream>> {
+        None
+    }
+
+    /// attach a connection detached by `take_stream` to connector
+    fn set_stream(&mut self, _stream: Box<dyn Stream>) {}
+
+    /// connect to the host of given url,
+    /// the connection will be secured by `handshake` if the scheme is `https`
+    fn connect(&mut self, url: &Url) -> Result<()> {
+        let addr = RequestParser::socket_addr(url)?;
+        self.connect_to(&addr)?;
+        if url.scheme() == "https" {
+            self.handshake(url)?;
+        }
+        Ok(())
+    }
+
+    /// start a tls session on the opened connection, the host of `url` is used as server name
+    fn handshake(&mut self, url: &Url) -> Result<()> {
+        Err(Error::from(TlsError::new(&format!("connector not support tls, can't request {}", url))))
+    }
 }
 
 pub trait HttpParser {
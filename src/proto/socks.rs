@@ -0,0 +1,441 @@
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+
+use url::{Host, Url};
+
+use crate::error::{Error, InvalidUrl, ProxyError, Result};
+use crate::proto::{Connector, RequestParser, Stream};
+
+const SOCKS5: u8 = 0x05;
+const SOCKS4: u8 = 0x04;
+const CMD_CONNECT: u8 = 0x01;
+const NO_AUTH: u8 = 0x00;
+const USERNAME_PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+const SOCKS4_GRANTED: u8 = 0x5a;
+
+/// the address sent to the proxy
+enum Target<'a> {
+    Addr(SocketAddr),
+    /// resolved by proxy
+    Domain(&'a str, u16),
+}
+
+/// Connector connect to the host through a SOCKS5 proxy,
+/// fall back to SOCKS4a if the proxy doesn't speak SOCKS5.
+///
+/// The connection to proxy is opened by the inner connector,
+/// and the tls session (for `https`) is started by the inner connector in the tunnel.
+///
+/// ```no_run
+/// use request_rs::produce::*;
+///
+/// let mut socks = Socks5Connector::new(HttpConnector::new(), "127.0.0.1:1080".parse().unwrap());
+/// socks.set_auth("user", "password");
+/// let mut client = HttpClient::from_connector(socks);
+/// let resp = client.send_request("http://www.example.com/", Method::GET, None, None).unwrap();
+/// ```
+pub struct Socks5Connector<C: Connector> {
+    inner: C,
+    proxy: SocketAddr,
+    auth: Option<(String, String)>,
+    remote_dns: bool,
+    socks4a_fallback: bool,
+}
+
+impl<C: Connector> Socks5Connector<C> {
+    /// Construct a new Socks5Connector use the SOCKS proxy at `proxy`
+    pub fn new(inner: C, proxy: SocketAddr) -> Self {
+        Self {
+            inner,
+            proxy,
+            auth: None,
+            remote_dns: true,
+            socks4a_fallback: true,
+        }
+    }
+
+    /// Set the username and password for SOCKS5 authentication,
+    /// the username is sent as user id for SOCKS4a
+    pub fn set_auth(&mut self, username: &str, password: &str) {
+        self.auth = Some((username.to_owned(), password.to_owned()));
+    }
+
+    /// Set whether the domain name is sent to the proxy and resolved by the proxy.
+    ///
+    /// If `false`, the domain name is resolved locally.
+    ///
+    /// Default is `true`.
+    pub fn set_remote_dns(&mut self, remote_dns: bool) {
+        self.remote_dns = remote_dns;
+    }
+
+    /// Set whether retry with SOCKS4a if the proxy doesn't speak SOCKS5.
+    ///
+    /// Default is `true`.
+    pub fn set_socks4a_fallback(&mut self, fallback: bool) {
+        self.socks4a_fallback = fallback;
+    }
+
+    /// the inner connector
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// open the connection to proxy and ask the proxy connect to `target`
+    fn connect_target(&mut self, target: &Target<'_>) -> Result<()> {
+        let proxy = self.proxy;
+        self.inner.connect_to(&proxy)?;
+        match self.socks5(target) {
+            Ok(true) => Ok(()),
+            Ok(false) if self.socks4a_fallback => {
+                debug!("proxy {} doesn't speak SOCKS5, fall back to SOCKS4a", proxy);
+                self.inner.take_stream();
+                self.inner.connect_to(&proxy)?;
+                self.socks4a(target).inspect_err(|_| {
+                    self.inner.take_stream();
+                })
+            }
+            Ok(false) => {
+                self.inner.take_stream();
+                Err(socks_error("proxy doesn't support SOCKS5"))
+            }
+            Err(e) => {
+                self.inner.take_stream();
+                Err(e)
+            }
+        }
+    }
+
+    /// SOCKS5 handshake as RFC 1928 and RFC 1929,
+    /// return false if the proxy doesn't speak SOCKS5
+    fn socks5(&mut self, target: &Target<'_>) -> Result<bool> {
+        let greeting: &[u8] = match self.auth {
+            Some(_) => &[SOCKS5, 2, NO_AUTH, USERNAME_PASSWORD],
+            None => &[SOCKS5, 1, NO_AUTH],
+        };
+        self.inner.write_all(greeting)?;
+        let mut reply = [0u8; 2];
+        match self.inner.read_exact(&mut reply) {
+            Ok(()) => {}
+            // SOCKS4 server close the connection when it receives the SOCKS5 greeting
+            Err(ref e) if is_rejected(e.kind()) => return Ok(false),
+            Err(e) => return Err(Error::from(e)),
+        }
+        if reply[0] != SOCKS5 {
+            return Ok(false);
+        }
+        match reply[1] {
+            NO_AUTH => {}
+            USERNAME_PASSWORD => {
+                let (username, password) = self.auth.clone()
+                    .ok_or_else(|| socks_error("proxy requires authentication"))?;
+                if username.len() > 255 || password.len() > 255 {
+                    return Err(socks_error("username or password is too long"));
+                }
+                let mut req = vec![0x01, username.len() as u8];
+                req.extend_from_slice(username.as_bytes());
+                req.push(password.len() as u8);
+                req.extend_from_slice(password.as_bytes());
+                self.inner.write_all(&req)?;
+                let mut reply = [0u8; 2];
+                self.inner.read_exact(&mut reply)?;
+                if reply[1] != 0x00 {
+                    return Err(socks_error("authentication failed"));
+                }
+            }
+            NO_ACCEPTABLE_METHODS => return Err(socks_error("no acceptable authentication methods")),
+            method => return Err(socks_error(&format!("unsupported authentication method {}", method))),
+        }
+
+        let mut req = vec![SOCKS5, CMD_CONNECT, 0x00];
+        let port = match *target {
+            Target::Addr(SocketAddr::V4(addr)) => {
+                req.push(ATYP_IPV4);
+                req.extend_from_slice(&addr.ip().octets());
+                addr.port()
+            }
+            Target::Addr(SocketAddr::V6(addr)) => {
+                req.push(ATYP_IPV6);
+                req.extend_from_slice(&addr.ip().octets());
+                addr.port()
+            }
+            Target::Domain(domain, port) => {
+                if domain.len() > 255 {
+                    return Err(socks_error("domain is too long"));
+                }
+                req.push(ATYP_DOMAIN);
+                req.push(domain.len() as u8);
+                req.extend_from_slice(domain.as_bytes());
+                port
+            }
+        };
+        req.extend_from_slice(&port.to_be_bytes());
+        self.inner.write_all(&req)?;
+
+        let mut reply = [0u8; 4];
+        self.inner.read_exact(&mut reply)?;
+        if reply[1] != 0x00 {
+            return Err(socks_error(socks5_reply_message(reply[1])));
+        }
+        // skip the bound address
+        let len = match reply[3] {
+            ATYP_IPV4 => 4,
+            ATYP_IPV6 => 16,
+            ATYP_DOMAIN => {
+                let mut len = [0u8; 1];
+                self.inner.read_exact(&mut len)?;
+                len[0] as usize
+            }
+            _ => return Err(socks_error("invalid address type in reply")),
+        };
+        let mut bound = vec![0u8; len + 2];
+        self.inner.read_exact(&mut bound)?;
+        Ok(true)
+    }
+
+    /// SOCKS4a handshake, the domain is resolved by proxy
+    fn socks4a(&mut self, target: &Target<'_>) -> Result<()> {
+        let mut req = vec![SOCKS4, CMD_CONNECT];
+        let domain = match *target {
+            Target::Addr(SocketAddr::V4(addr)) => {
+                req.extend_from_slice(&addr.port().to_be_bytes());
+                req.extend_from_slice(&addr.ip().octets());
+                None
+            }
+            Target::Addr(SocketAddr::V6(_)) => return Err(socks_error("SOCKS4a doesn't support IPv6")),
+            Target::Domain(domain, port) => {
+                req.extend_from_slice(&port.to_be_bytes());
+                // 0.0.0.x tells the proxy to resolve the domain
+                req.extend_from_slice(&[0, 0, 0, 1]);
+                Some(domain)
+            }
+        };
+        if let Some((ref username, _)) = self.auth {
+            req.extend_from_slice(username.as_bytes());
+        }
+        req.push(0);
+        if let Some(domain) = domain {
+            req.extend_from_slice(domain.as_bytes());
+            req.push(0);
+        }
+        self.inner.write_all(&req)?;
+
+        let mut reply = [0u8; 8];
+        self.inner.read_exact(&mut reply)?;
+        if reply[1] != SOCKS4_GRANTED {
+            return Err(socks_error(&format!("request rejected or failed ({:#x})", reply[1])));
+        }
+        Ok(())
+    }
+}
+
+/// the connection is closed or reset by peer
+fn is_rejected(kind: ErrorKind) -> bool {
+    matches!(kind, ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted)
+}
+
+fn socks_error(msg: &str) -> Error {
+    Error::from(ProxyError::new(&format!("socks: {}", msg)))
+}
+
+/// RFC 1928 section 6
+fn socks5_reply_message(rep: u8) -> &'static str {
+    match rep {
+        0x01 => "general SOCKS server failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+impl<C: Connector + fmt::Debug> fmt::Debug for Socks5Connector<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Socks5Connector")
+            .field("inner", &self.inner)
+            .field("proxy", &self.proxy)
+            .field("auth", &self.auth.as_ref().map(|(username, _)| username))
+            .field("remote_dns", &self.remote_dns)
+            .field("socks4a_fallback", &self.socks4a_fallback)
+            .finish()
+    }
+}
+
+impl<C: Connector> Connector for Socks5Connector<C> {
+    /// open tcp connection to the proxy
+    fn create_connection(&mut self, _socket_addr: &SocketAddr) -> Result<TcpStream> {
+        let proxy = self.proxy;
+        self.inner.create_connection(&proxy)
+    }
+
+    fn connect_to(&mut self, addr: &SocketAddr) -> Result<()> {
+        self.connect_target(&Target::Addr(*addr))
+    }
+
+    fn take_stream(&mut self) -> Option<Box<dyn Stream>> {
+        self.inner.take_stream()
+    }
+
+    fn set_stream(&mut self, stream: Box<dyn Stream>) {
+        self.inner.set_stream(stream)
+    }
+
+    /// the domain is sent to proxy if remote dns is enabled
+    fn connect(&mut self, url: &Url) -> Result<()> {
+        let port = url.port_or_known_default().ok_or(Error::from(InvalidUrl::new("missing port")))?;
+        match url.host() {
+            Some(Host::Domain(domain)) if self.remote_dns => self.connect_target(&Target::Domain(domain, port))?,
+            Some(Host::Ipv4(ip)) => self.connect_to(&SocketAddr::new(IpAddr::V4(ip), port))?,
+            Some(Host::Ipv6(ip)) => self.connect_to(&SocketAddr::new(IpAddr::V6(ip), port))?,
+            Some(Host::Domain(_)) => self.connect_to(&RequestParser::socket_addr(url)?)?,
+            None => return Err(Error::from(InvalidUrl::new("missing host"))),
+        }
+        if url.scheme() == "https" {
+            self.handshake(url)?;
+        }
+        Ok(())
+    }
+
+    fn handshake(&mut self, url: &Url) -> Result<()> {
+        self.inner.handshake(url)
+    }
+}
+
+impl<C: Connector> Read for Socks5Connector<C> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<C: Connector> Write for Socks5Connector<C> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::thread;
+
+    use crate::client::tests::read_request;
+    use crate::error::ProxyError;
+    use crate::produce::*;
+    use crate::proto::HttpConnector;
+
+    use super::Socks5Connector;
+
+    const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+
+    /// run the SOCKS stub, return the proxy address and the received requests
+    fn stub<F>(handler: F) -> (SocketAddr, thread::JoinHandle<Vec<u8>>)
+        where F: FnOnce(&TcpListener) -> Vec<u8> + Send + 'static {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        (addr, thread::spawn(move || handler(&listener)))
+    }
+
+    fn read_bytes(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; len];
+        stream.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    /// serve the http request in the tunnel
+    fn serve_http(stream: &mut TcpStream) -> Vec<u8> {
+        let req = read_request(stream).unwrap();
+        stream.write_all(RESPONSE).unwrap();
+        req
+    }
+
+    fn client(proxy: SocketAddr, auth: Option<(&str, &str)>) -> HttpClient<Socks5Connector<HttpConnector>> {
+        let mut socks = Socks5Connector::new(HttpConnector::new(), proxy);
+        if let Some((username, password)) = auth {
+            socks.set_auth(username, password);
+        }
+        HttpClient::from_connector(socks)
+    }
+
+    #[test]
+    fn test_socks5_remote_dns() {
+        let (proxy, server) = stub(|listener| {
+            let (mut stream, _) = listener.accept().unwrap();
+            assert_eq!(vec![5, 1, 0], read_bytes(&mut stream, 3));
+            stream.write_all(&[5, 0]).unwrap();
+            assert_eq!(b"\x05\x01\x00\x03\x0bexample.com\x00\x50".to_vec(), read_bytes(&mut stream, 18));
+            stream.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0x1f, 0x90]).unwrap();
+            serve_http(&mut stream)
+        });
+        let resp = client(proxy, None).send_request("http://example.com/", Method::GET, None, None).expect("request failed");
+        assert_eq!(StatusCode::OK, resp.status());
+        assert!(server.join().unwrap().starts_with(b"GET / HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn test_socks5_username_password() {
+        let (proxy, server) = stub(|listener| {
+            let (mut stream, _) = listener.accept().unwrap();
+            assert_eq!(vec![5, 2, 0, 2], read_bytes(&mut stream, 4));
+            stream.write_all(&[5, 2]).unwrap();
+            assert_eq!(b"\x01\x04user\x04pass".to_vec(), read_bytes(&mut stream, 11));
+            stream.write_all(&[1, 0]).unwrap();
+            assert_eq!(b"\x05\x01\x00\x03\x0bexample.com\x00\x50".to_vec(), read_bytes(&mut stream, 18));
+            // bound address as domain
+            stream.write_all(b"\x05\x00\x00\x03\x05proxy\x04\x38").unwrap();
+            serve_http(&mut stream)
+        });
+        let resp = client(proxy, Some(("user", "pass"))).send_request("http://example.com/", Method::GET, None, None)
+            .expect("request failed");
+        assert_eq!(StatusCode::OK, resp.status());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_socks5_connection_refused() {
+        let (proxy, server) = stub(|listener| {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_bytes(&mut stream, 3);
+            stream.write_all(&[5, 0]).unwrap();
+            read_bytes(&mut stream, 18);
+            stream.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+            Vec::new()
+        });
+        let err = client(proxy, None).send_request("http://example.com/", Method::GET, None, None).unwrap_err();
+        assert!(err.is::<ProxyError>());
+        assert_eq!("socks: connection refused", err.to_string());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_fall_back_to_socks4a() {
+        let (proxy, server) = stub(|listener| {
+            // SOCKS4 server close the connection when it receives the SOCKS5 greeting
+            let (mut stream, _) = listener.accept().unwrap();
+            read_bytes(&mut stream, 4);
+            drop(stream);
+
+            let (mut stream, _) = listener.accept().unwrap();
+            assert_eq!(b"\x04\x01\x00\x50\x00\x00\x00\x01user\x00example.com\x00".to_vec(), read_bytes(&mut stream, 25));
+            stream.write_all(&[0, 0x5a, 0, 0, 0, 0, 0, 0]).unwrap();
+            serve_http(&mut stream)
+        });
+        let resp = client(proxy, Some(("user", "pass"))).send_request("http://example.com/", Method::GET, None, None)
+            .expect("request failed");
+        assert_eq!(StatusCode::OK, resp.status());
+        server.join().unwrap();
+    }
+}
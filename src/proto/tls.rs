@@ -0,0 +1,329 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::pki_types::pem::PemObject;
+use url::{Host, Url};
+
+use crate::error::{Error, InvalidUrl, Result, TlsError};
+use crate::proto::{Connector, Stream};
+use crate::proto::http1::conn::{HttpConfig, tcp_connect};
+
+/// the tls configuration for https client
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    root_store: RootCertStore,
+}
+
+impl TlsConfig {
+    /// Construct a new TlsConfig trust the Mozilla root certificates (from `webpki-roots`)
+    pub fn new() -> Self {
+        let mut root_store = RootCertStore::empty();
+        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        Self { root_store }
+    }
+
+    /// Construct a TlsConfig without any trusted root certificate
+    pub fn empty() -> Self {
+        Self { root_store: RootCertStore::empty() }
+    }
+
+    /// trust the given DER encoded root certificate
+    pub fn add_der_certificate(&mut self, der: &[u8]) -> Result<()> {
+        self.root_store.add(CertificateDer::from(der.to_vec()))?;
+        Ok(())
+    }
+
+    /// trust all root certificates in given PEM data, return the number of added certificates
+    pub fn add_pem_certificates(&mut self, pem: &[u8]) -> Result<usize> {
+        let mut added = 0;
+        for cert in CertificateDer::pem_slice_iter(pem) {
+            let cert = cert.map_err(|e| Error::from(TlsError::new(e.to_string().as_str())))?;
+            self.root_store.add(cert)?;
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    /// the number of trusted root certificates
+    pub fn root_certificates(&self) -> usize {
+        self.root_store.len()
+    }
+
+    /// build rustls client config
+    fn client_config(&self) -> Result<Arc<ClientConfig>> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(self.root_store.clone())
+            .with_no_client_auth();
+        Ok(Arc::new(config))
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// the stream of `TlsConnector`, plain tcp for `http` and tls for `https`
+#[derive(Debug)]
+pub enum MaybeTlsStream {
+    /// plain tcp stream
+    Plain(TcpStream),
+    /// tcp stream secured by tls
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+/// Connector support both `http` and `https`,
+/// the tls session is established automatically when the url scheme is `https`
+pub struct TlsConnector {
+    config: HttpConfig,
+    tls: Arc<ClientConfig>,
+    stream: Option<MaybeTlsStream>,
+}
+
+impl TlsConnector {
+    /// Construct a new TlsConnector trust the Mozilla root certificates
+    pub fn new() -> Result<Self> {
+        Self::with_config(HttpConfig::default(), TlsConfig::new())
+    }
+
+    /// Construct a new TlsConnector use given http config and tls config
+    pub fn with_config(config: HttpConfig, tls: TlsConfig) -> Result<Self> {
+        Ok(Self {
+            config,
+            tls: tls.client_config()?,
+            stream: None,
+        })
+    }
+
+    /// return true if the opened connection is secured by tls
+    pub fn is_secure(&self) -> bool {
+        matches!(self.stream, Some(MaybeTlsStream::Tls(_)))
+    }
+
+    /// get the server name for tls from url host
+    fn server_name(url: &Url) -> Result<ServerName<'static>> {
+        match url.host() {
+            Some(Host::Domain(domain)) => ServerName::try_from(domain.to_owned())
+                .map_err(|e| Error::from(TlsError::new(e.to_string().as_str()))),
+            Some(Host::Ipv4(ip)) => Ok(ServerName::from(std::net::IpAddr::V4(ip))),
+            Some(Host::Ipv6(ip)) => Ok(ServerName::from(std::net::IpAddr::V6(ip))),
+            None => Err(Error::from(InvalidUrl::new("missing host"))),
+        }
+    }
+}
+
+impl fmt::Debug for TlsConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConnector")
+            .field("config", &self.config)
+            .field("connected", &self.stream.is_some())
+            .field("secure", &self.is_secure())
+            .finish()
+    }
+}
+
+impl Connector for TlsConnector {
+    fn create_connection(&mut self, socket_addr: &SocketAddr) -> Result<TcpStream> {
+        tcp_connect(&self.config, socket_addr)
+    }
+
+    fn connect_to(&mut self, addr: &SocketAddr) -> Result<()> {
+        let stream = self.create_connection(addr)?;
+        self.stream = Some(MaybeTlsStream::Plain(stream));
+        Ok(())
+    }
+
+    fn take_stream(&mut self) -> Option<Box<dyn Stream>> {
+        self.stream.take().map(|stream| Box::new(stream) as Box<dyn Stream>)
+    }
+
+    fn set_stream(&mut self, stream: Box<dyn Stream>) {
+        match stream.into_any().downcast::<MaybeTlsStream>() {
+            Ok(stream) => self.stream = Some(*stream),
+            Err(_) => debug!("the connection is not opened by TlsConnector"),
+        }
+    }
+
+    fn handshake(&mut self, url: &Url) -> Result<()> {
+        let mut tcp = match self.stream.take() {
+            Some(MaybeTlsStream::Plain(tcp)) => tcp,
+            Some(MaybeTlsStream::Tls(_)) => {
+                return Err(Error::from(TlsError::new("tls session already established")));
+            }
+            None => panic!("handshake failed! no connection opened, please open connection first"),
+        };
+        let mut conn = ClientConnection::new(self.tls.clone(), Self::server_name(url)?)?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut tcp).map_err(|e| {
+                // keep the message of certificate or protocol errors
+                match e.get_ref().and_then(|inner| inner.downcast_ref::<rustls::Error>()) {
+                    Some(tls_err) => Error::from(tls_err.clone()),
+                    None => Error::from(e),
+                }
+            })?;
+        }
+        self.stream = Some(MaybeTlsStream::Tls(Box::new(StreamOwned::new(conn, tcp))));
+        Ok(())
+    }
+}
+
+impl Read for MaybeTlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            MaybeTlsStream::Plain(stream) => stream.read(buf),
+            MaybeTlsStream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for MaybeTlsStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            MaybeTlsStream::Plain(stream) => stream.write(buf),
+            MaybeTlsStream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            MaybeTlsStream::Plain(stream) => stream.flush(),
+            MaybeTlsStream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+impl Read for TlsConnector {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if let Some(ref mut stream) = self.stream {
+            return stream.read(buf);
+        }
+        panic!("read failed! no connection opened, please open connection first")
+    }
+}
+
+impl Write for TlsConnector {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(ref mut stream) = self.stream {
+            return stream.write(buf);
+        }
+        panic!("write failed! no connection opened, please open connection first")
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if let Some(ref mut stream) = self.stream {
+            return stream.flush();
+        }
+        panic!("flush failed! no connection opened, please open connection first")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use rustls::{ServerConfig, ServerConnection, StreamOwned};
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+    use crate::produce::*;
+
+    use super::TlsConfig;
+
+    /// generate a self-signed CA and a `localhost` certificate signed by it
+    fn certificates() -> (String, CertificateDer<'static>, PrivateKeyDer<'static>) {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec!["localhost".to_string()]).unwrap()
+            .signed_by(&key, &ca, &ca_key).unwrap();
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der()));
+        (ca.pem(), cert.der().clone(), key)
+    }
+
+    /// serve one https request, return the port.
+    /// if `proxy` is true, the server act as a proxy accept `CONNECT` then serve the request in the tunnel
+    fn serve_once(cert: CertificateDer<'static>, key: PrivateKeyDer<'static>, proxy: bool) -> (u16, thread::JoinHandle<Option<String>>) {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions().unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key).unwrap();
+        let listener = TcpListener::bind("localhost:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (mut tcp, _) = listener.accept().unwrap();
+            if proxy {
+                let connect = crate::client::tests::read_request(&mut tcp).unwrap();
+                assert!(connect.starts_with(b"CONNECT localhost:443 HTTP/1.1\r\n"));
+                tcp.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n").unwrap();
+            }
+            let conn = ServerConnection::new(Arc::new(config)).unwrap();
+            let mut stream = StreamOwned::new(conn, tcp);
+            let mut buf = [0u8; 1024];
+            // handshake failed
+            if stream.read(&mut buf).is_err() {
+                return None;
+            }
+            let sni = stream.conn.server_name().map(|name| name.to_string());
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok").unwrap();
+            stream.conn.send_close_notify();
+            stream.flush().unwrap();
+            sni
+        });
+        (port, handle)
+    }
+
+    #[test]
+    fn test_https_with_self_signed_ca() {
+        let (ca, cert, key) = certificates();
+        let (port, server) = serve_once(cert, key, false);
+
+        let mut tls = TlsConfig::empty();
+        assert_eq!(1, tls.add_pem_certificates(ca.as_bytes()).unwrap());
+        let mut client = HttpClient::with_tls_config(Default::default(), tls).unwrap();
+        let url = format!("https://localhost:{}/", port);
+        let resp = client.send_request(url.as_str(), Method::GET, None, None).expect("request failed");
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!(Some("localhost".to_string()), server.join().unwrap());
+    }
+
+    #[test]
+    fn test_https_untrusted_certificate() {
+        let (_, cert, key) = certificates();
+        let (port, server) = serve_once(cert, key, false);
+
+        let mut client = HttpClient::with_tls_config(Default::default(), TlsConfig::empty()).unwrap();
+        let url = format!("https://localhost:{}/", port);
+        let err = client.send_request(url.as_str(), Method::GET, None, None).unwrap_err();
+        assert!(err.is::<crate::error::TlsError>());
+        assert_eq!(None, server.join().unwrap());
+    }
+
+    #[test]
+    fn test_https_through_connect_proxy() {
+        let (ca, cert, key) = certificates();
+        let (port, server) = serve_once(cert, key, true);
+
+        let mut tls = TlsConfig::empty();
+        tls.add_pem_certificates(ca.as_bytes()).unwrap();
+        let mut client = HttpClient::with_tls_config(Default::default(), tls).unwrap();
+        client.add_proxy(Proxy::https(&format!("http://localhost:{}", port)).unwrap());
+        let resp = client.send_request("https://localhost/", Method::GET, None, None).expect("request failed");
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!(Some("localhost".to_string()), server.join().unwrap());
+    }
+}
@@ -0,0 +1,200 @@
+use bytes::{Buf, BytesMut};
+
+use crate::error::{Error, WebSocketError};
+
+use super::socket::CloseFrame;
+
+/// the max payload length of control frames
+pub(crate) const MAX_CONTROL_PAYLOAD: usize = 125;
+
+/// the frame opcodes, RFC 6455 section 5.2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OpCode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl OpCode {
+    fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            0x0 => Some(OpCode::Continuation),
+            0x1 => Some(OpCode::Text),
+            0x2 => Some(OpCode::Binary),
+            0x8 => Some(OpCode::Close),
+            0x9 => Some(OpCode::Ping),
+            0xA => Some(OpCode::Pong),
+            _ => None,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            OpCode::Continuation => 0x0,
+            OpCode::Text => 0x1,
+            OpCode::Binary => 0x2,
+            OpCode::Close => 0x8,
+            OpCode::Ping => 0x9,
+            OpCode::Pong => 0xA,
+        }
+    }
+
+    pub fn is_control(self) -> bool {
+        matches!(self, OpCode::Close | OpCode::Ping | OpCode::Pong)
+    }
+}
+
+/// a WebSocket frame, the payload is unmasked
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Frame {
+    pub fin: bool,
+    pub opcode: OpCode,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(fin: bool, opcode: OpCode, payload: Vec<u8>) -> Self {
+        Self { fin, opcode, payload }
+    }
+
+    /// write the frame to `dst`, the payload is masked by `mask` if given
+    pub fn encode(&self, mask: Option<[u8; 4]>, dst: &mut Vec<u8>) {
+        let fin = if self.fin { 0x80 } else { 0x00 };
+        dst.push(fin | self.opcode.as_u8());
+        let masked = if mask.is_some() { 0x80 } else { 0x00 };
+        let len = self.payload.len();
+        if len < 126 {
+            dst.push(masked | len as u8);
+        } else if len <= u16::MAX as usize {
+            dst.push(masked | 126);
+            dst.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            dst.push(masked | 127);
+            dst.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+        match mask {
+            Some(mask) => {
+                dst.extend_from_slice(&mask);
+                dst.extend(self.payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+            }
+            None => dst.extend_from_slice(&self.payload),
+        }
+    }
+
+    /// parse a frame from `buf`, `None` if more data is needed.
+    /// the masked frame is rejected unless `allow_mask`, the server must not mask frames.
+    /// the error comes with the status code to close the connection
+    pub fn parse(buf: &mut BytesMut, allow_mask: bool, max_payload: usize) -> Result<Option<Frame>, (u16, Error)> {
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+        let fin = buf[0] & 0x80 != 0;
+        if buf[0] & 0x70 != 0 {
+            return Err((CloseFrame::PROTOCOL_ERROR, protocol_error("reserved bits are set without extension")));
+        }
+        let opcode = OpCode::from_u8(buf[0] & 0x0F)
+            .ok_or_else(|| (CloseFrame::PROTOCOL_ERROR, protocol_error(&format!("unknown opcode {:#x}", buf[0] & 0x0F))))?;
+        let masked = buf[1] & 0x80 != 0;
+        if masked && !allow_mask {
+            return Err((CloseFrame::PROTOCOL_ERROR, protocol_error("the frame from server is masked")));
+        }
+        let (len, mut header_len) = match buf[1] & 0x7F {
+            126 => {
+                if buf.len() < 4 {
+                    return Ok(None);
+                }
+                (u64::from(u16::from_be_bytes([buf[2], buf[3]])), 4)
+            }
+            127 => {
+                if buf.len() < 10 {
+                    return Ok(None);
+                }
+                let mut len = [0u8; 8];
+                len.copy_from_slice(&buf[2..10]);
+                (u64::from_be_bytes(len), 10)
+            }
+            len => (u64::from(len), 2),
+        };
+        if opcode.is_control() && (!fin || len > MAX_CONTROL_PAYLOAD as u64) {
+            return Err((CloseFrame::PROTOCOL_ERROR, protocol_error("the control frame is fragmented or too long")));
+        }
+        if len > max_payload as u64 {
+            let e = Error::from(WebSocketError::new(&format!("the frame of {} bytes exceeds the limit {}", len, max_payload)));
+            return Err((CloseFrame::MESSAGE_TOO_BIG, e));
+        }
+        let mask = if masked {
+            if buf.len() < header_len + 4 {
+                return Ok(None);
+            }
+            let mut mask = [0u8; 4];
+            mask.copy_from_slice(&buf[header_len..header_len + 4]);
+            header_len += 4;
+            Some(mask)
+        } else {
+            None
+        };
+        let len = len as usize;
+        if buf.len() < header_len + len {
+            return Ok(None);
+        }
+        buf.advance(header_len);
+        let mut payload = buf.split_to(len).to_vec();
+        if let Some(mask) = mask {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+        Ok(Some(Frame { fin, opcode, payload }))
+    }
+}
+
+/// the error violating the protocol, the connection is closed with code 1002
+pub(crate) fn protocol_error(msg: &str) -> Error {
+    Error::from(WebSocketError::new(&format!("protocol error: {}", msg)))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use super::{Frame, OpCode};
+    use super::super::socket::CloseFrame;
+
+    #[test]
+    fn test_encode_and_parse() {
+        // RFC 6455 section 5.7, a single-frame masked text message
+        let frame = Frame::new(true, OpCode::Text, b"Hello".to_vec());
+        let mut dst = Vec::new();
+        frame.encode(Some([0x37, 0xfa, 0x21, 0x3d]), &mut dst);
+        assert_eq!(vec![0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58], dst);
+        let mut buf = BytesMut::from(&dst[..]);
+        assert!(Frame::parse(&mut buf, false, 1024).is_err());
+        assert_eq!(Some(frame), Frame::parse(&mut buf, true, 1024).unwrap());
+        assert!(buf.is_empty());
+
+        // the 16-bit length of an unmasked binary frame
+        let frame = Frame::new(false, OpCode::Binary, vec![1; 256]);
+        let mut dst = Vec::new();
+        frame.encode(None, &mut dst);
+        assert_eq!(&[0x02, 0x7E, 0x01, 0x00], &dst[..4]);
+        let mut buf = BytesMut::from(&dst[..100]);
+        assert_eq!(None, Frame::parse(&mut buf, false, 1024).unwrap());
+        let mut buf = BytesMut::from(&dst[..]);
+        assert_eq!(Some(frame), Frame::parse(&mut buf, false, 1024).unwrap());
+        let mut buf = BytesMut::from(&dst[..]);
+        assert_eq!(CloseFrame::MESSAGE_TOO_BIG, Frame::parse(&mut buf, false, 255).unwrap_err().0);
+    }
+
+    #[test]
+    fn test_invalid_frames() {
+        // fragmented ping
+        assert_eq!(CloseFrame::PROTOCOL_ERROR, Frame::parse(&mut BytesMut::from(&[0x09, 0x00][..]), false, 1024).unwrap_err().0);
+        // reserved bit
+        assert!(Frame::parse(&mut BytesMut::from(&[0xC1, 0x00][..]), false, 1024).is_err());
+        // unknown opcode
+        assert!(Frame::parse(&mut BytesMut::from(&[0x83, 0x00][..]), false, 1024).is_err());
+    }
+}
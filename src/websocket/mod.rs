@@ -0,0 +1,5 @@
+pub(crate) use self::socket::{check_handshake, generate_key};
+pub use self::socket::{CloseFrame, Message, WebSocket};
+
+mod frame;
+mod socket;
@@ -0,0 +1,582 @@
+use std::fmt;
+use std::io::{Read, Write};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use bytes::BytesMut;
+use rand::RngCore;
+use sha1::{Digest, Sha1};
+
+use crate::error::{Error, IoError, Result, WebSocketError};
+use crate::header::{CONNECTION, HeaderValue, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_PROTOCOL, UPGRADE};
+use crate::response::Response;
+use crate::status::StatusCode;
+
+use super::frame::{Frame, MAX_CONTROL_PAYLOAD, OpCode, protocol_error};
+
+/// the GUID appended to the key, RFC 6455 section 1.3
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// the default payload size of the frames sent, the larger message is fragmented
+const DEFAULT_FRAME_SIZE: usize = 64 << 10;
+/// the default max size of the messages received
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 << 20;
+const READ_BUFFER_SIZE: usize = 8192;
+
+/// the random `Sec-WebSocket-Key` of handshake
+pub(crate) fn generate_key() -> String {
+    let mut key = [0u8; 16];
+    rand::rng().fill_bytes(&mut key);
+    STANDARD.encode(key)
+}
+
+/// the `Sec-WebSocket-Accept` expected for `key`
+pub(crate) fn accept_key(key: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key.as_bytes());
+    sha1.update(GUID.as_bytes());
+    STANDARD.encode(sha1.finalize())
+}
+
+/// check the response of opening handshake sent with `key` and requested `protocols`
+pub(crate) fn check_handshake(resp: &Response<()>, key: &str, protocols: Option<&HeaderValue>) -> Result<()> {
+    if resp.status() != StatusCode::SWITCHING_PROTOCOLS {
+        return Err(handshake_error(&format!("the server responded {} instead of switching protocols", resp.status())));
+    }
+    let headers = resp.headers();
+    let upgrade = headers.get(UPGRADE).map(|value| value.as_bytes().eq_ignore_ascii_case(b"websocket")).unwrap_or(false);
+    let connection = headers.get_all(CONNECTION).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+    if !upgrade || !connection {
+        return Err(handshake_error("missing `Upgrade: websocket` or `Connection: Upgrade` in response"));
+    }
+    match headers.get(SEC_WEBSOCKET_ACCEPT) {
+        Some(accept) if accept.as_bytes() == accept_key(key).as_bytes() => {}
+        _ => return Err(handshake_error("invalid `Sec-WebSocket-Accept` in response")),
+    }
+    if let Some(protocol) = headers.get(SEC_WEBSOCKET_PROTOCOL) {
+        let requested = protocols.and_then(|value| value.to_str().ok())
+            .map(|value| value.split(',').any(|requested| requested.trim().as_bytes() == protocol.as_bytes()))
+            .unwrap_or(false);
+        if !requested {
+            return Err(handshake_error("the server selected a subprotocol which is not requested"));
+        }
+    }
+    Ok(())
+}
+
+fn handshake_error(msg: &str) -> Error {
+    Error::from(WebSocketError::new(&format!("websocket handshake failed: {}", msg)))
+}
+
+/// The status code and reason of close frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloseFrame {
+    /// the status code, RFC 6455 section 7.4
+    pub code: u16,
+    /// the UTF-8 reason, may be empty
+    pub reason: String,
+}
+
+impl CloseFrame {
+    /// the purpose of connection is fulfilled
+    pub const NORMAL: u16 = 1000;
+    /// the endpoint is going away, such as a server going down
+    pub const GOING_AWAY: u16 = 1001;
+    /// the endpoint received a frame violating the protocol
+    pub const PROTOCOL_ERROR: u16 = 1002;
+    /// the type of data can't be accepted
+    pub const UNSUPPORTED: u16 = 1003;
+    /// the text message is not valid UTF-8
+    pub const INVALID_PAYLOAD: u16 = 1007;
+    /// the message violates the policy of endpoint
+    pub const POLICY_VIOLATION: u16 = 1008;
+    /// the message is too big to process
+    pub const MESSAGE_TOO_BIG: u16 = 1009;
+
+    /// Create a close frame with status code and reason
+    pub fn new(code: u16, reason: &str) -> Self {
+        Self { code, reason: reason.to_owned() }
+    }
+
+    /// the codes which can be sent in close frame, RFC 6455 section 7.4
+    fn is_valid_code(code: u16) -> bool {
+        matches!(code, 1000..=1003 | 1007..=1011 | 3000..=4999)
+    }
+}
+
+/// The message sent or received by `WebSocket`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// the UTF-8 text message
+    Text(String),
+    /// the binary message
+    Binary(Vec<u8>),
+    /// the ping received is answered by pong automatically
+    Ping(Vec<u8>),
+    /// the pong, either the answer of ping or unsolicited heartbeat
+    Pong(Vec<u8>),
+    /// the close frame without status code has no `CloseFrame`
+    Close(Option<CloseFrame>),
+}
+
+/// A WebSocket connection opened by `HttpClient::websocket`.
+///
+/// The frames sent are masked, the message larger than the frame size is fragmented.
+/// The ping is answered by pong and the close frame is echoed by `read`.
+///
+/// ```no_run
+/// use request_rs::produce::*;
+///
+/// let mut client = HttpClient::http();
+/// let req = Request::builder()
+///     .uri(Url::parse("ws://echo.example.com/chat").unwrap())
+///     .header("Sec-WebSocket-Protocol", "chat")
+///     .body(Body::empty()).unwrap();
+/// let mut socket = client.websocket(req).expect("handshake failed");
+/// socket.send(Message::Text("hello".to_owned())).unwrap();
+/// loop {
+///     match socket.read().unwrap() {
+///         Message::Text(text) => {
+///             println!("{}", text);
+///             socket.close(CloseFrame::NORMAL, "bye").unwrap();
+///         }
+///         Message::Close(frame) => {
+///             println!("closed {:?}", frame);
+///             break;
+///         }
+///         _ => {}
+///     }
+/// }
+/// ```
+pub struct WebSocket<S> {
+    stream: S,
+    buf: BytesMut,
+    response: Response<()>,
+    /// the opcode and data of the fragmented message being received
+    fragments: Option<(OpCode, Vec<u8>)>,
+    frame_size: usize,
+    max_message_size: usize,
+    close_sent: bool,
+    close_received: bool,
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    /// the connection after opening handshake, `buf` holds the data received after the response head
+    pub(crate) fn new(stream: S, buf: BytesMut, response: Response<()>) -> Self {
+        Self {
+            stream,
+            buf,
+            response,
+            fragments: None,
+            frame_size: DEFAULT_FRAME_SIZE,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            close_sent: false,
+            close_received: false,
+        }
+    }
+
+    /// the response of opening handshake
+    pub fn response(&self) -> &Response<()> {
+        &self.response
+    }
+
+    /// the subprotocol selected by server
+    pub fn protocol(&self) -> Option<&str> {
+        self.response.headers().get(SEC_WEBSOCKET_PROTOCOL).and_then(|value| value.to_str().ok())
+    }
+
+    /// Set the max payload size of the data frames sent, the larger message is fragmented.
+    ///
+    /// Default is 64 KiB.
+    pub fn set_frame_size(&mut self, size: usize) {
+        self.frame_size = std::cmp::max(size, 1);
+    }
+
+    /// Set the max size of the messages received, the connection is closed with 1009 if it's exceeded.
+    ///
+    /// Default is 64 MiB.
+    pub fn set_max_message_size(&mut self, size: usize) {
+        self.max_message_size = size;
+    }
+
+    /// send a message, no message can be sent after close frame
+    pub fn send(&mut self, msg: Message) -> Result<()> {
+        if self.close_sent {
+            return Err(Error::from(WebSocketError::new("the close frame has been sent")));
+        }
+        let (opcode, data) = match msg {
+            Message::Text(text) => (OpCode::Text, text.into_bytes()),
+            Message::Binary(data) => (OpCode::Binary, data),
+            Message::Ping(data) => return self.send_control(OpCode::Ping, data),
+            Message::Pong(data) => return self.send_control(OpCode::Pong, data),
+            Message::Close(frame) => {
+                let mut payload = Vec::new();
+                if let Some(frame) = frame {
+                    if !CloseFrame::is_valid_code(frame.code) {
+                        return Err(Error::from(WebSocketError::new(&format!("the close code {} can't be sent", frame.code))));
+                    }
+                    if frame.reason.len() > MAX_CONTROL_PAYLOAD - 2 {
+                        return Err(Error::from(WebSocketError::new(&format!("the close reason exceeds {} bytes", MAX_CONTROL_PAYLOAD - 2))));
+                    }
+                    payload.extend_from_slice(&frame.code.to_be_bytes());
+                    payload.extend_from_slice(frame.reason.as_bytes());
+                }
+                self.close_sent = true;
+                return self.send_control(OpCode::Close, payload);
+            }
+        };
+        if data.is_empty() {
+            return self.write_frame(&Frame::new(true, opcode, data));
+        }
+        let count = data.len().div_ceil(self.frame_size);
+        for (i, chunk) in data.chunks(self.frame_size).enumerate() {
+            let opcode = if i == 0 { opcode } else { OpCode::Continuation };
+            self.write_frame(&Frame::new(i + 1 == count, opcode, chunk.to_vec()))?;
+        }
+        Ok(())
+    }
+
+    /// send the close frame, the connection is closed after the close frame of server is read.
+    ///
+    /// The `code` must be valid to send and the `reason` is limited to 123 bytes,
+    /// otherwise an error is returned and the connection is still open.
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<()> {
+        self.send(Message::Close(Some(CloseFrame::new(code, reason))))
+    }
+
+    /// read the next message, the fragmented message is read as a whole.
+    ///
+    /// The connection is closed with the status code of error if server violates the protocol,
+    /// no message can be read after close frame.
+    pub fn read(&mut self) -> Result<Message> {
+        if self.close_received {
+            return Err(Error::from(WebSocketError::new("the close frame has been received")));
+        }
+        loop {
+            let frame = self.read_frame()?;
+            match frame.opcode {
+                OpCode::Ping => {
+                    if !self.close_sent {
+                        self.send_control(OpCode::Pong, frame.payload.clone())?;
+                    }
+                    return Ok(Message::Ping(frame.payload));
+                }
+                OpCode::Pong => return Ok(Message::Pong(frame.payload)),
+                OpCode::Close => return self.on_close(frame.payload),
+                OpCode::Text | OpCode::Binary => {
+                    if self.fragments.is_some() {
+                        return Err(self.fail(CloseFrame::PROTOCOL_ERROR, protocol_error("new message before the fragmented message finished")));
+                    }
+                    if frame.fin {
+                        return self.message(frame.opcode, frame.payload);
+                    }
+                    self.fragments = Some((frame.opcode, frame.payload));
+                }
+                OpCode::Continuation => {
+                    let (opcode, mut data) = match self.fragments.take() {
+                        Some(fragments) => fragments,
+                        None => return Err(self.fail(CloseFrame::PROTOCOL_ERROR, protocol_error("continuation frame without message"))),
+                    };
+                    if data.len() + frame.payload.len() > self.max_message_size {
+                        let e = Error::from(WebSocketError::new(&format!("the message exceeds the limit {}", self.max_message_size)));
+                        return Err(self.fail(CloseFrame::MESSAGE_TOO_BIG, e));
+                    }
+                    data.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        return self.message(opcode, data);
+                    }
+                    self.fragments = Some((opcode, data));
+                }
+            }
+        }
+    }
+
+    /// the underlying connection
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    fn message(&mut self, opcode: OpCode, data: Vec<u8>) -> Result<Message> {
+        if opcode == OpCode::Binary {
+            return Ok(Message::Binary(data));
+        }
+        match String::from_utf8(data) {
+            Ok(text) => Ok(Message::Text(text)),
+            Err(_) => Err(self.fail(CloseFrame::INVALID_PAYLOAD, protocol_error("the text message is not valid UTF-8"))),
+        }
+    }
+
+    /// validate the close frame of server and echo it
+    fn on_close(&mut self, payload: Vec<u8>) -> Result<Message> {
+        let frame = match payload.len() {
+            0 => None,
+            1 => return Err(self.fail(CloseFrame::PROTOCOL_ERROR, protocol_error("invalid close frame"))),
+            _ => {
+                let code = u16::from_be_bytes([payload[0], payload[1]]);
+                if !CloseFrame::is_valid_code(code) {
+                    return Err(self.fail(CloseFrame::PROTOCOL_ERROR, protocol_error(&format!("invalid close code {}", code))));
+                }
+                match String::from_utf8(payload[2..].to_vec()) {
+                    Ok(reason) => Some(CloseFrame { code, reason }),
+                    Err(_) => return Err(self.fail(CloseFrame::INVALID_PAYLOAD, protocol_error("the close reason is not valid UTF-8"))),
+                }
+            }
+        };
+        self.close_received = true;
+        if !self.close_sent {
+            self.close_sent = true;
+            let mut echo = Vec::new();
+            if let Some(ref frame) = frame {
+                echo.extend_from_slice(&frame.code.to_be_bytes());
+            }
+            self.send_control(OpCode::Close, echo)?;
+        }
+        Ok(Message::Close(frame))
+    }
+
+    /// close the connection with `code` because of `err`
+    fn fail(&mut self, code: u16, err: Error) -> Error {
+        self.close_received = true;
+        if !self.close_sent {
+            self.close_sent = true;
+            let _ = self.send_control(OpCode::Close, code.to_be_bytes().to_vec());
+        }
+        err
+    }
+
+    fn send_control(&mut self, opcode: OpCode, payload: Vec<u8>) -> Result<()> {
+        if payload.len() > MAX_CONTROL_PAYLOAD {
+            return Err(Error::from(WebSocketError::new(&format!("the payload of control frame exceeds {} bytes", MAX_CONTROL_PAYLOAD))));
+        }
+        self.write_frame(&Frame::new(true, opcode, payload))
+    }
+
+    /// write the frame masked by a random key
+    fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        let mut mask = [0u8; 4];
+        rand::rng().fill_bytes(&mut mask);
+        let mut out = Vec::with_capacity(frame.payload.len() + 14);
+        frame.encode(Some(mask), &mut out);
+        self.stream.write_all(&out)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// read a frame, the connection is closed with the status code of invalid frame.
+    /// no close frame is sent after I/O error, the connection may be broken
+    fn read_frame(&mut self) -> Result<Frame> {
+        loop {
+            match Frame::parse(&mut self.buf, false, self.max_message_size) {
+                Ok(Some(frame)) => return Ok(frame),
+                Ok(None) => {}
+                Err((code, e)) => return Err(self.fail(code, e)),
+            }
+            let mut chunk = [0u8; READ_BUFFER_SIZE];
+            let n = self.stream.read(&mut chunk)?;
+            if n == 0 {
+                return Err(Error::from(IoError::from_kind(std::io::ErrorKind::UnexpectedEof)));
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+impl<S> fmt::Debug for WebSocket<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocket")
+            .field("response", &self.response)
+            .field("close_sent", &self.close_sent)
+            .field("close_received", &self.close_received)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    use bytes::BytesMut;
+    use url::Url;
+
+    use crate::body::Body;
+    use crate::client::tests::{local_client, read_request};
+    use crate::header::HeaderValue;
+    use crate::request::Request;
+    use crate::response::Response;
+    use crate::status::StatusCode;
+
+    use super::{accept_key, check_handshake, CloseFrame, Message};
+    use super::super::frame::{Frame, OpCode};
+
+    fn read_frame(stream: &mut TcpStream, buf: &mut BytesMut) -> Frame {
+        loop {
+            if let Some(frame) = Frame::parse(buf, true, 1 << 20).unwrap() {
+                return frame;
+            }
+            let mut chunk = [0u8; 1024];
+            let n = stream.read(&mut chunk).unwrap();
+            assert!(n > 0, "the connection closed");
+            buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    fn write_frame(stream: &mut TcpStream, fin: bool, opcode: OpCode, payload: &[u8]) {
+        let mut dst = Vec::new();
+        Frame::new(fin, opcode, payload.to_vec()).encode(None, &mut dst);
+        stream.write_all(&dst).unwrap();
+    }
+
+    /// answer the opening handshake with extra `headers` and `frames`, return the request
+    fn accept(stream: &mut TcpStream, headers: &str, frames: &[u8]) -> String {
+        let req = String::from_utf8(read_request(stream).unwrap()).unwrap();
+        let key = req.lines()
+            .find(|line| line.to_lowercase().starts_with("sec-websocket-key:"))
+            .map(|line| line["sec-websocket-key:".len()..].trim().to_owned())
+            .unwrap();
+        let mut resp = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+            Sec-WebSocket-Accept: {}\r\n{}\r\n", accept_key(&key), headers).into_bytes();
+        resp.extend_from_slice(frames);
+        stream.write_all(&resp).unwrap();
+        req
+    }
+
+    #[test]
+    fn test_check_handshake() {
+        // RFC 6455 section 1.3
+        assert_eq!("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=", accept_key("dGhlIHNhbXBsZSBub25jZQ=="));
+        let response = |status: u16, accept: &str, protocol: Option<&str>| {
+            let mut builder = Response::builder()
+                .status(StatusCode::from_u16(status).unwrap())
+                .header("Upgrade", "websocket")
+                .header("Connection", "keep-alive, Upgrade")
+                .header("Sec-WebSocket-Accept", accept);
+            if let Some(protocol) = protocol {
+                builder = builder.header("Sec-WebSocket-Protocol", protocol);
+            }
+            builder.body(()).unwrap()
+        };
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let accept = "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
+        let requested = HeaderValue::from_static("chat, superchat");
+        assert!(check_handshake(&response(101, accept, None), key, None).is_ok());
+        assert!(check_handshake(&response(101, accept, Some("chat")), key, Some(&requested)).is_ok());
+        assert!(check_handshake(&response(101, accept, Some("mqtt")), key, Some(&requested)).is_err());
+        assert!(check_handshake(&response(101, accept, Some("chat")), key, None).is_err());
+        assert!(check_handshake(&response(101, "invalid", None), key, None).is_err());
+        assert!(check_handshake(&response(200, accept, None), key, None).is_err());
+    }
+
+    #[test]
+    fn test_websocket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("ws://{}/chat", listener.local_addr().unwrap())).unwrap();
+        // echo the messages, a ping is sent along with the handshake response
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut ping = Vec::new();
+            Frame::new(true, OpCode::Ping, b"hb".to_vec()).encode(None, &mut ping);
+            let req = accept(&mut stream, "Sec-WebSocket-Protocol: chat\r\n", &ping);
+
+            let mut buf = BytesMut::new();
+            let mut received = Vec::new();
+            let mut message = Vec::new();
+            loop {
+                let frame = read_frame(&mut stream, &mut buf);
+                received.push((frame.fin, frame.opcode));
+                match frame.opcode {
+                    OpCode::Close => {
+                        write_frame(&mut stream, true, OpCode::Close, &frame.payload);
+                        break;
+                    }
+                    OpCode::Text | OpCode::Continuation => {
+                        message.extend_from_slice(&frame.payload);
+                        if frame.fin {
+                            let (first, rest) = message.split_at(3);
+                            write_frame(&mut stream, false, OpCode::Text, first);
+                            write_frame(&mut stream, false, OpCode::Continuation, &rest[..1]);
+                            write_frame(&mut stream, true, OpCode::Continuation, &rest[1..]);
+                            message.clear();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            (req, received)
+        });
+
+        let req = Request::builder()
+            .uri(url)
+            .header("Sec-WebSocket-Protocol", "chat")
+            .body(Body::empty()).unwrap();
+        let mut socket = local_client().websocket(req).unwrap();
+        assert_eq!(Some("chat"), socket.protocol());
+        socket.set_frame_size(4);
+        assert_eq!(Message::Ping(b"hb".to_vec()), socket.read().unwrap());
+        socket.send(Message::Text("hello websocket".to_owned())).unwrap();
+        assert_eq!(Message::Text("hello websocket".to_owned()), socket.read().unwrap());
+        assert!(socket.close(1005, "bye").is_err());
+        assert!(socket.close(CloseFrame::NORMAL, &"x".repeat(124)).is_err());
+        socket.close(CloseFrame::NORMAL, "bye").unwrap();
+        assert!(socket.send(Message::Text("late".to_owned())).is_err());
+        assert_eq!(Message::Close(Some(CloseFrame::new(CloseFrame::NORMAL, "bye"))), socket.read().unwrap());
+        assert!(socket.read().is_err());
+
+        let (req, received) = server.join().unwrap();
+        let req = req.to_lowercase();
+        assert!(req.starts_with("get /chat http/1.1\r\n"));
+        assert!(req.contains("upgrade: websocket\r\n"));
+        assert!(req.contains("sec-websocket-version: 13\r\n"));
+        assert_eq!(vec![
+            (true, OpCode::Pong),
+            (false, OpCode::Text),
+            (false, OpCode::Continuation),
+            (false, OpCode::Continuation),
+            (true, OpCode::Continuation),
+            (true, OpCode::Close),
+        ], received);
+    }
+
+    #[test]
+    fn test_protocol_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("ws://{}/", listener.local_addr().unwrap())).unwrap();
+        // an invalid close code is answered by 1002
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            accept(&mut stream, "", &[]);
+            write_frame(&mut stream, true, OpCode::Close, &1005u16.to_be_bytes());
+            let frame = read_frame(&mut stream, &mut BytesMut::new());
+            (frame.opcode, frame.payload)
+        });
+
+        let req = Request::builder().uri(url).body(Body::empty()).unwrap();
+        let mut socket = local_client().websocket(req).unwrap();
+        assert_eq!(None, socket.protocol());
+        assert!(socket.read().is_err());
+        assert_eq!((OpCode::Close, CloseFrame::PROTOCOL_ERROR.to_be_bytes().to_vec()), server.join().unwrap());
+    }
+
+    #[test]
+    fn test_message_too_big() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("ws://{}/", listener.local_addr().unwrap())).unwrap();
+        // the frame exceeds the limit is answered by 1009, the connection is closed then
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            accept(&mut stream, "", &[]);
+            write_frame(&mut stream, true, OpCode::Binary, &[0; 300]);
+            let frame = read_frame(&mut stream, &mut BytesMut::new());
+            (frame.opcode, frame.payload)
+        });
+
+        let req = Request::builder().uri(url).body(Body::empty()).unwrap();
+        let mut socket = local_client().websocket(req).unwrap();
+        socket.set_max_message_size(255);
+        assert!(socket.read().is_err());
+        assert_eq!((OpCode::Close, CloseFrame::MESSAGE_TOO_BIG.to_be_bytes().to_vec()), server.join().unwrap());
+        assert!(socket.read().is_err());
+    }
+}